@@ -6,37 +6,391 @@ use opencv::{
     prelude::*,
     videoio::{self, VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst, CAP_ANY},
 };
-use std::time::Duration;
+use std::borrow::Cow;
+use std::io::Read;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use clap::{Parser, Subcommand};
+use node_utils::{retry_with_backoff, BackoffConfig, FrameEncoding, ENCODING_PARAM};
+
+/// 摄像头节点命令行：不带子命令时作为 Dora 节点运行
+#[derive(Parser)]
+#[command(name = "camera_node", about = "Camera source node for Dora")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 从摄像头抓取一帧并保存为图片
+    Capture {
+        /// 输出图片路径
+        path: String,
+        /// 摄像头设备索引
+        #[arg(long, default_value_t = 0)]
+        device: i32,
+    },
+}
 
 fn main() {
-    println!("Camera node: Starting...");
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Capture { path, device }) => {
+            if let Err(e) = run_capture(&path, device) {
+                eprintln!("Camera node: Capture failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        None => run_node(),
+    }
+}
+
+/// 子命令 capture：抓取单帧并写入文件
+fn run_capture(path: &str, device: i32) -> opencv::Result<()> {
+    let mut cam = VideoCapture::new(device, CAP_ANY)?;
+    if !cam.is_opened()? {
+        return Err(opencv::Error::new(opencv::core::StsError, format!("Failed to open camera {}", device)));
+    }
+    let mut frame = Mat::default();
+    // 丢弃前几帧，等待曝光稳定
+    for _ in 0..5 {
+        cam.read(&mut frame)?;
+    }
+    if !cam.read(&mut frame)? || frame.empty() {
+        return Err(opencv::Error::new(opencv::core::StsError, "Failed to read frame".to_string()));
+    }
+    opencv::imgcodecs::imwrite(path, &frame, &opencv::core::Vector::new())?;
+    println!("Camera node: Saved {}x{} frame to {}", frame.cols(), frame.rows(), path);
+    Ok(())
+}
+
+/// 帧读取结果
+enum ReadOutcome {
+    Frame,   // 成功读取一帧
+    Failed,  // 本次读取失败，可以稍后重试
+    Ended,   // 帧来源已结束（如管道 EOF）
+}
+
+/// 帧来源：OpenCV 摄像头、视频文件或网络流，或从字节流读取的定长原始帧
+enum FrameSource {
+    Device(VideoCapture),
+    // finite 为 true 时（本地视频文件）读取失败即视为播放结束
+    Video { capture: VideoCapture, finite: bool },
+    Raw(RawFrameReader<Box<dyn Read>>),
+}
+
+impl FrameSource {
+    fn read(&mut self, frame: &mut Mat) -> ReadOutcome {
+        match self {
+            FrameSource::Device(cam) => match cam.read(frame) {
+                Ok(true) => ReadOutcome::Frame,
+                _ => ReadOutcome::Failed,
+            },
+            FrameSource::Video { capture, finite } => match capture.read(frame) {
+                Ok(true) if !frame.empty() => ReadOutcome::Frame,
+                _ if *finite => ReadOutcome::Ended,
+                _ => ReadOutcome::Failed,
+            },
+            FrameSource::Raw(reader) => match reader.next_frame() {
+                Ok(Some(bytes)) => match reader.format.to_bgr(bytes, reader.width, reader.height)
+                    .and_then(|bgr| raw_frame_to_mat(&bgr, reader.width, reader.height)) {
+                    Ok(mat) => {
+                        *frame = mat;
+                        ReadOutcome::Frame
+                    }
+                    Err(e) => {
+                        eprintln!("Camera node: Failed to wrap raw frame: {}", e);
+                        ReadOutcome::Failed
+                    }
+                },
+                Ok(None) => ReadOutcome::Ended,
+                Err(e) => {
+                    eprintln!("Camera node: Failed to read raw frame: {}", e);
+                    ReadOutcome::Ended
+                }
+            },
+        }
+    }
+}
+
+/// YUV→RGB 转换所用的色彩矩阵（有限范围，Y 16-235）
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum YuvMatrix {
+    Bt601,  // 标清
+    Bt709,  // 高清
+}
+
+impl YuvMatrix {
+    /// 按 CAMERA_YUV_MATRIX 选择，`auto`（默认）时高度不低于 720 的画面用 BT.709，否则用 BT.601
+    fn select(spec: &str, height: i32) -> Result<Self, String> {
+        match spec {
+            "bt601" => Ok(YuvMatrix::Bt601),
+            "bt709" => Ok(YuvMatrix::Bt709),
+            "" | "auto" => Ok(if height >= 720 { YuvMatrix::Bt709 } else { YuvMatrix::Bt601 }),
+            other => Err(format!("unknown YUV matrix '{}', expected auto, bt601 or bt709", other)),
+        }
+    }
     
-    // 初始化Dora节点
-    let (mut node, mut event_stream) = match DoraNode::init_from_env() {
-        Ok(n) => n,
-        Err(e) => {
-            eprintln!("Camera node: Failed to initialize DoraNode: {}", e);
-            std::process::exit(1);
+    /// (V→R, U→G, V→G, U→B) 系数
+    fn coefficients(self) -> (f32, f32, f32, f32) {
+        match self {
+            YuvMatrix::Bt601 => (1.596, 0.392, 0.813, 2.017),
+            YuvMatrix::Bt709 => (1.793, 0.213, 0.533, 2.112),
         }
-    };
+    }
+    
+    /// 将一个 YUV 像素转换为 BGR
+    fn yuv_to_bgr(self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let (rv, gu, gv, bu) = self.coefficients();
+        let y = 1.164 * (y as f32 - 16.0);
+        let u = u as f32 - 128.0;
+        let v = v as f32 - 128.0;
+        let clamp = |x: f32| x.round().clamp(0.0, 255.0) as u8;
+        [clamp(y + bu * u), clamp(y - gu * u - gv * v), clamp(y + rv * v)]
+    }
+}
 
-    println!("Camera node: Dora node initialized successfully");
+/// 原始帧的像素格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RawFormat {
+    Bgr,
+    Nv12(YuvMatrix),  // Y 平面后接交错的 UV 平面，转换时使用指定的色彩矩阵
+}
+
+impl RawFormat {
+    fn frame_size(self, width: i32, height: i32) -> usize {
+        let pixels = (width * height) as usize;
+        match self {
+            RawFormat::Bgr => pixels * 3,
+            RawFormat::Nv12(_) => pixels * 3 / 2,
+        }
+    }
+    
+    /// 转换为 BGR 字节
+    fn to_bgr(self, bytes: Vec<u8>, width: i32, height: i32) -> opencv::Result<Vec<u8>> {
+        match self {
+            RawFormat::Bgr => Ok(bytes),
+            RawFormat::Nv12(matrix) => {
+                if width % 2 != 0 || height % 2 != 0 {
+                    return Err(opencv::Error::new(opencv::core::StsBadSize,
+                        format!("NV12 frames need even dimensions, got {}x{}", width, height)));
+                }
+                Ok(nv12_to_bgr(&bytes, width as usize, height as usize, matrix))
+            }
+        }
+    }
+}
 
-    // 初始化摄像头
-    println!("Camera node: Attempting to open camera at index 0");
-    let mut cam = VideoCapture::new(0, CAP_ANY).unwrap();
-    if !cam.is_opened().unwrap() {
-        eprintln!("Camera node: Failed to open camera");
-        std::process::exit(1);
+/// NV12 转 BGR：每 2x2 像素共享一组 UV
+fn nv12_to_bgr(bytes: &[u8], width: usize, height: usize, matrix: YuvMatrix) -> Vec<u8> {
+    let (luma, chroma) = bytes.split_at(width * height);
+    let mut bgr = Vec::with_capacity(width * height * 3);
+    for row in 0..height {
+        for col in 0..width {
+            let uv = (row / 2) * width + (col / 2) * 2;
+            bgr.extend_from_slice(&matrix.yuv_to_bgr(luma[row * width + col], chroma[uv], chroma[uv + 1]));
+        }
+    }
+    bgr
+}
+
+/// 从任意字节流中读取 width x height 的原始帧（BGR 或 NV12），
+/// 部分读取时持续缓冲直到凑满一帧，遇到 EOF 时结束（不完整的尾帧被丢弃）
+struct RawFrameReader<R: Read> {
+    reader: R,
+    width: i32,
+    height: i32,
+    format: RawFormat,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> RawFrameReader<R> {
+    fn new(reader: R, width: i32, height: i32, format: RawFormat) -> Self {
+        Self {
+            reader,
+            width,
+            height,
+            format,
+            buffer: vec![0; format.frame_size(width, height)],
+        }
+    }
+    
+    /// 读取下一帧，EOF 时返回 None
+    fn next_frame(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut filled = 0;
+        while filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => {
+                    if filled > 0 {
+                        eprintln!("Camera node: Discarding incomplete trailing frame ({} of {} bytes)", filled, self.buffer.len());
+                    }
+                    return Ok(None);
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Some(self.buffer.clone()))
     }
+}
 
-    // 设置摄像头分辨率
-    cam.set(videoio::CAP_PROP_FRAME_WIDTH, 640.0).unwrap();
-    cam.set(videoio::CAP_PROP_FRAME_HEIGHT, 480.0).unwrap();
+/// 将原始 BGR 字节包装为 Mat
+fn raw_frame_to_mat(bytes: &[u8], width: i32, height: i32) -> opencv::Result<Mat> {
+    let mut mat = Mat::new_rows_cols_with_default(height, width, opencv::core::CV_8UC3, Scalar::all(0.0))?;
+    mat.data_bytes_mut()?.copy_from_slice(bytes);
+    Ok(mat)
+}
+
+/// 保持宽高比缩放到不超过 max_w x max_h 的尺寸（至少 1 像素），不放大
+fn thumbnail_size(src_w: i32, src_h: i32, max_w: i32, max_h: i32) -> (i32, i32) {
+    let scale = (max_w as f64 / src_w as f64).min(max_h as f64 / src_h as f64).min(1.0);
+    (
+        ((src_w as f64 * scale).round() as i32).max(1),
+        ((src_h as f64 * scale).round() as i32).max(1),
+    )
+}
+
+/// 缩小帧并返回连续的 BGR 字节
+fn make_thumbnail(frame: &Mat, width: i32, height: i32) -> opencv::Result<Vec<u8>> {
+    let mut thumb = Mat::default();
+    imgproc::resize(frame, &mut thumb, opencv::core::Size::new(width, height), 0.0, 0.0, imgproc::INTER_AREA)?;
+    Ok(thumb.data_bytes()?.to_vec())
+}
+
+/// 将帧压缩为 JPEG，quality 取 0-100
+fn encode_jpeg(frame: &Mat, quality: i32) -> opencv::Result<Vec<u8>> {
+    let params = opencv::core::Vector::<i32>::from_slice(&[opencv::imgcodecs::IMWRITE_JPEG_QUALITY, quality]);
+    let mut buffer = opencv::core::Vector::<u8>::new();
+    if !opencv::imgcodecs::imencode(".jpg", frame, &mut buffer, &params)? {
+        return Err(opencv::Error::new(opencv::core::StsError, "imencode returned false"));
+    }
+    Ok(buffer.to_vec())
+}
+
+/// 按配置的编码取得要发送的帧数据及实际使用的编码；JPEG 编码失败时退回原始像素
+fn frame_payload(frame: &Mat, encoding: FrameEncoding, jpeg_quality: i32) -> opencv::Result<(Cow<'_, [u8]>, FrameEncoding)> {
+    if encoding == FrameEncoding::Jpeg {
+        match encode_jpeg(frame, jpeg_quality) {
+            Ok(bytes) => return Ok((Cow::Owned(bytes), FrameEncoding::Jpeg)),
+            Err(e) => eprintln!("Camera node: Failed to encode frame as JPEG, sending raw pixels: {}", e),
+        }
+    }
+    Ok((Cow::Borrowed(frame.data_bytes()?), FrameEncoding::Raw))
+}
+
+/// 读取环境变量并解析，缺失或非法时使用默认值
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => match value.trim().parse::<T>() {
+            Ok(v) => v,
+            Err(_) => {
+                eprintln!("Camera node: Invalid value '{}' for {}, using default", value, key);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+/// 根据 CAMERA_RAW_FORMAT（`bgr` 默认，或 `nv12`）与 CAMERA_YUV_MATRIX 确定原始帧格式
+fn raw_format_from_env(height: i32) -> Result<RawFormat, String> {
+    match std::env::var("CAMERA_RAW_FORMAT").unwrap_or_default().as_str() {
+        "" | "bgr" => Ok(RawFormat::Bgr),
+        "nv12" => {
+            let matrix = YuvMatrix::select(&std::env::var("CAMERA_YUV_MATRIX").unwrap_or_default(), height)?;
+            Ok(RawFormat::Nv12(matrix))
+        }
+        other => Err(format!("unknown raw format '{}', expected bgr or nv12", other)),
+    }
+}
+
+/// CAMERA_SOURCE 解析出的帧来源
+#[derive(Debug, PartialEq)]
+enum SourceSpec {
+    Stdin,
+    Pipe(String),
+    Device(i32),
+    File(String),    // 本地视频文件（路径或 file:// URL），播放结束即停止
+    Stream(String),  // rtsp:// 等网络流，读取失败时重试
+}
+
+impl SourceSpec {
+    /// 解析来源字符串：
+    /// - `stdin`：从标准输入读取原始帧（如 `ffmpeg ... -f rawvideo -pix_fmt bgr24 -`）
+    /// - `pipe:<path>`：从命名管道读取原始帧
+    /// - 整数或未设置：打开对应索引的摄像头（默认 0 号）
+    /// - `file://<path>` 或其他不带 URL scheme 的值：本地视频文件
+    /// - 其他 `<scheme>://...`：网络流（如 `rtsp://...`）
+    fn parse(spec: &str) -> Self {
+        let spec = spec.trim();
+        if spec == "stdin" {
+            return SourceSpec::Stdin;
+        }
+        if let Some(path) = spec.strip_prefix("pipe:") {
+            return SourceSpec::Pipe(path.to_string());
+        }
+        if spec.is_empty() {
+            return SourceSpec::Device(0);
+        }
+        if let Ok(index) = spec.parse::<i32>() {
+            return SourceSpec::Device(index);
+        }
+        if let Some(path) = spec.strip_prefix("file://") {
+            return SourceSpec::File(path.to_string());
+        }
+        match spec.split_once("://") {
+            Some((scheme, _)) if !scheme.is_empty()
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')) => {
+                SourceSpec::Stream(spec.to_string())
+            }
+            _ => SourceSpec::File(spec.to_string()),
+        }
+    }
+}
+
+/// 根据 CAMERA_SOURCE 打开帧来源，格式见 [`SourceSpec::parse`]
+fn open_source(spec: &str) -> Result<FrameSource, String> {
+    let device = match SourceSpec::parse(spec) {
+        SourceSpec::Stdin => return open_raw(None),
+        SourceSpec::Pipe(path) => return open_raw(Some(&path)),
+        SourceSpec::File(path) => return open_video(&path, true),
+        SourceSpec::Stream(url) => return open_video(&url, false),
+        SourceSpec::Device(index) => index,
+    };
+    
+    let width: f64 = env_or("CAMERA_WIDTH", 640.0);
+    let height: f64 = env_or("CAMERA_HEIGHT", 480.0);
+    let fps: f64 = env_or("CAMERA_FPS", 30.0);
+    
+    // 初始化摄像头（失败时按指数退避重试）
+    let backoff = BackoffConfig::from_env("CAMERA_RETRY");
+    let mut cam = retry_with_backoff(&backoff, "Camera node: Open camera", |attempt| {
+        println!("Camera node: Attempting to open camera at index {} (attempt {})", device, attempt);
+        let cam = VideoCapture::new(device, CAP_ANY).map_err(|e| e.to_string())?;
+        if cam.is_opened().unwrap_or(false) {
+            Ok(cam)
+        } else {
+            Err("failed to open camera".to_string())
+        }
+    })?;
+
+    // 设置摄像头分辨率与帧率；设备不支持时仅告警，下面读取实际生效的值
+    let mut properties = vec![(videoio::CAP_PROP_FRAME_WIDTH, "width", width), (videoio::CAP_PROP_FRAME_HEIGHT, "height", height)];
+    if fps > 0.0 {
+        properties.push((videoio::CAP_PROP_FPS, "fps", fps));
+    }
+    for (property, name, value) in properties {
+        match cam.set(property, value) {
+            Ok(true) => {}
+            Ok(false) => eprintln!("Camera node: Camera rejected {} {}, using device default", name, value),
+            Err(e) => eprintln!("Camera node: Failed to set {} {}: {}", name, value, e),
+        }
+    }
 
     // 获取实际分辨率
-    let width = cam.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap_or(640.0);
-    let height = cam.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap_or(480.0);
+    let width = cam.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap_or(width);
+    let height = cam.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap_or(height);
     println!("Camera node: Camera opened successfully - {}x{}", width as i32, height as i32);
 
     // 预热摄像头
@@ -49,103 +403,137 @@ fn main() {
         }
     }
     println!("Camera node: Warmup complete");
+    
+    Ok(FrameSource::Device(cam))
+}
+
+/// 从标准输入或命名管道读取定长原始帧
+fn open_raw(pipe: Option<&str>) -> Result<FrameSource, String> {
+    let raw_width: i32 = env_or("CAMERA_RAW_WIDTH", 640);
+    let raw_height: i32 = env_or("CAMERA_RAW_HEIGHT", 480);
+    let format = raw_format_from_env(raw_height)?;
+    let reader: Box<dyn Read> = match pipe {
+        Some(path) => {
+            println!("Camera node: Reading {}x{} raw {:?} frames from pipe {}", raw_width, raw_height, format, path);
+            Box::new(std::fs::File::open(path).map_err(|e| format!("failed to open {}: {}", path, e))?)
+        }
+        None => {
+            println!("Camera node: Reading {}x{} raw {:?} frames from stdin", raw_width, raw_height, format);
+            Box::new(std::io::stdin())
+        }
+    };
+    Ok(FrameSource::Raw(RawFrameReader::new(reader, raw_width, raw_height, format)))
+}
+
+/// 打开视频文件或网络流（如 rtsp://），finite 为 false 的网络流视为不会结束，读取失败时重试
+fn open_video(spec: &str, finite: bool) -> Result<FrameSource, String> {
+    let backoff = BackoffConfig::from_env("CAMERA_RETRY");
+    let capture = retry_with_backoff(&backoff, "Camera node: Open video source", |attempt| {
+        println!("Camera node: Attempting to open video source {} (attempt {})", spec, attempt);
+        let capture = VideoCapture::from_file(spec, CAP_ANY).map_err(|e| e.to_string())?;
+        if capture.is_opened().unwrap_or(false) {
+            Ok(capture)
+        } else {
+            Err(format!("failed to open video source {}", spec))
+        }
+    })?;
+    
+    let width = capture.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap_or(0.0);
+    let height = capture.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap_or(0.0);
+    let fps = capture.get(videoio::CAP_PROP_FPS).unwrap_or(0.0);
+    println!("Camera node: Video source opened - {}x{} @ {:.1} FPS{}",
+             width as i32, height as i32, fps, if finite { " (file)" } else { " (stream)" });
+    
+    Ok(FrameSource::Video { capture, finite })
+}
+
+/// 计算下一次采集时刻：按固定间隔递推，落后超过一个间隔时从当前时刻重新计时，避免连续补帧
+fn next_capture_due(previous: Instant, interval: Duration, now: Instant) -> Instant {
+    let next = previous + interval;
+    if next <= now {
+        now + interval
+    } else {
+        next
+    }
+}
+
+fn run_node() {
+    println!("Camera node: Starting...");
+    
+    // 初始化Dora节点
+    let (mut node, mut event_stream) = match DoraNode::init_from_env() {
+        Ok(n) => n,
+        Err(e) => {
+            eprintln!("Camera node: Failed to initialize DoraNode: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Camera node: Dora node initialized successfully");
+
+    // 初始化帧来源：默认摄像头，也可打开视频文件/RTSP 流，或从 stdin / 命名管道读取原始 BGR/NV12 帧
+    let source_spec = std::env::var("CAMERA_SOURCE").unwrap_or_default();
+    let mut source = match open_source(&source_spec) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Camera node: Failed to open frame source: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // 初始化OpenCV窗口
     highgui::named_window("Camera Feed", highgui::WINDOW_AUTOSIZE).unwrap();
 
+    // 按检测节点的工作分辨率缩小后再发送 frame（保持宽高比），0 表示按采集分辨率发送；
+    // 可选地在 frame_full 输出另发一份原分辨率帧，只给可视化节点订阅
+    let output_max = (env_or("CAMERA_OUTPUT_WIDTH", 0), env_or("CAMERA_OUTPUT_HEIGHT", 0));
+    let fullres_output: bool = env_or("CAMERA_FULLRES_OUTPUT", false);
+
+    // 可选的缩略图输出：保持宽高比缩放到不超过该尺寸，0 表示不输出
+    let thumbnail_max = (env_or("CAMERA_THUMBNAIL_WIDTH", 0), env_or("CAMERA_THUMBNAIL_HEIGHT", 0));
+
+    // frame / frame_full 输出的编码：raw（默认）或 jpeg，下游按元数据中的 encoding 参数解码
+    let frame_encoding: FrameEncoding = env_or("CAMERA_FRAME_ENCODING", FrameEncoding::Raw);
+    let jpeg_quality: i32 = env_or("CAMERA_JPEG_QUALITY", 90).clamp(0, 100);
+    if frame_encoding == FrameEncoding::Jpeg {
+        println!("Camera node: Sending JPEG-encoded frames (quality {})", jpeg_quality);
+    }
+
+    // 采集间隔由 CAMERA_FPS 决定，为 0 时不等待、连续采集（如视频文件全速回放）
+    let camera_fps: f64 = env_or("CAMERA_FPS", 30.0);
+    let capture_interval = (camera_fps > 0.0).then(|| Duration::from_secs_f64(1.0 / camera_fps));
+    let mut next_capture = Instant::now();
+
     let mut frame_count = 0;
     let start_time = std::time::Instant::now();
 
-    // 主循环 - 等待输入事件来触发帧捕获
+    // 主循环：按 CAMERA_FPS 的间隔采集帧，等待期间处理 Dora 事件
     loop {
-        if let Some(event) = event_stream.recv_timeout(Duration::from_millis(10)) {
+        // 等到下一次采集时刻，期间收到的事件立即处理
+        let wait = capture_interval
+            .map(|_| next_capture.saturating_duration_since(Instant::now()))
+            .unwrap_or(Duration::ZERO);
+        if let Some(event) = event_stream.recv_timeout(wait) {
             match event {
-                Event::Input { id, data: _, metadata: _ } => {
-                    if id.as_str() == "tick" {
-                        // 读取帧
-                        let mut frame = Mat::default();
-                        if !cam.read(&mut frame).unwrap() {
-                            eprintln!("Camera node: Failed to read frame");
-                            continue;
-                        }
-
-                        if frame.size().unwrap().width <= 0 || frame.size().unwrap().height <= 0 {
-                            eprintln!("Camera node: Empty frame received");
-                            continue;
-                        }
-
-                        // 在图像上添加文本
-                        imgproc::put_text(
-                            &mut frame,
-                            &format!("Frame: {}", frame_count),
-                            opencv::core::Point::new(10, 30),
-                            imgproc::FONT_HERSHEY_SIMPLEX,
-                            1.0,
-                            Scalar::new(255.0, 255.0, 200.0, 0.0), // 白色文本
-                            2,
-                            imgproc::LINE_AA,
-                            false,
-                        ).unwrap();
-
-                        // 显示图像
-            //            highgui::imshow("Camera Feed", &frame).unwrap();
-
-                        // 将OpenCV Mat转换为字节数组 - BGR格式
-                        let size = frame.size().unwrap();
-                        let channels = frame.channels();
-                        let expected_size = (size.width * size.height * channels) as usize;
-                        
-                        let mat_data = unsafe {
-                            std::slice::from_raw_parts(
-                                frame.data(), 
-                                expected_size
-                            ).to_vec()
-                        };
-                        
-                        // 验证数据大小
-                        let actual_width = size.width as i32;
-                        let actual_height = size.height as i32;
-                        let actual_channels = channels;
-                        let calculated_size = (actual_width * actual_height * actual_channels) as usize;
-                        
-                        println!("Camera node: Frame size: {}, Data length: {}, Calculated: {}x{}x{}={}", 
-                                frame_count, mat_data.len(), actual_width, actual_height, actual_channels, calculated_size);
-
-                        // 使用正确的API发送数据
-                        let output_id = DataId::from("frame".to_string());
-                     //   let parameters = MetadataParameters::default();
-                        
-                        let mut parameters = MetadataParameters::new();
-                        parameters.insert("width".to_string(), dora_node_api::Parameter::String(actual_width.to_string()));
-                        parameters.insert("height".to_string(), dora_node_api::Parameter::String(actual_height.to_string()));
-                        parameters.insert("channels".to_string(), dora_node_api::Parameter::String(actual_channels.to_string()));
-                        parameters.insert("frame_id".to_string(), dora_node_api::Parameter::String(frame_count.to_string()));
-                        
-                        match node.send_output_bytes(output_id, parameters, mat_data.len(), &mat_data) {
-                            Ok(_) => {
-                                frame_count += 1;
-                                println!("Camera node: Sent frame {}", frame_count);
-                            },
-                            Err(e) => {
-                                eprintln!("Camera node: Failed to send frame: {}", e);
-                                // 继续运行，不退出
-                            }
-                        }
-                    }
-                }
                 Event::Stop(_) => {
                     println!("Camera node: Received stop event after sending {} frames", frame_count);
                     break;
                 }
                 Event::Error(e) => {
                     println!("Camera node: Received error event: {}", e);
-                    continue; // 继续运行
                 }
                 _ => {
                     println!("Camera node: Received other event: {:?}", event);
                 }
             }
         }
+        if let Some(interval) = capture_interval {
+            let now = Instant::now();
+            if now < next_capture {
+                continue;
+            }
+            next_capture = next_capture_due(next_capture, interval, now);
+        }
 
         // 检查是否有按键
         let key = highgui::wait_key(1).unwrap_or(0);
@@ -154,8 +542,157 @@ fn main() {
             break;
         }
 
-        // 控制帧率
-        std::thread::sleep(Duration::from_millis(33)); // ~30 FPS
+        // 读取帧
+        let mut frame = Mat::default();
+        match source.read(&mut frame) {
+            ReadOutcome::Frame => {}
+            ReadOutcome::Failed => {
+                eprintln!("Camera node: Failed to read frame");
+                continue;
+            }
+            ReadOutcome::Ended => {
+                println!("Camera node: Frame source ended after {} frames, stopping", frame_count);
+                break;
+            }
+        }
+
+        if frame.size().unwrap().width <= 0 || frame.size().unwrap().height <= 0 {
+            eprintln!("Camera node: Empty frame received");
+            continue;
+        }
+
+        // 在图像上添加文本
+        imgproc::put_text(
+            &mut frame,
+            &format!("Frame: {}", frame_count),
+            opencv::core::Point::new(10, 30),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            1.0,
+            Scalar::new(255.0, 255.0, 200.0, 0.0), // 白色文本
+            2,
+            imgproc::LINE_AA,
+            false,
+        ).unwrap();
+
+        // 显示图像
+        //            highgui::imshow("Camera Feed", &frame).unwrap();
+
+        // 缩小到输出分辨率，原分辨率帧保留给 frame_full
+        let full_frame = if output_max.0 > 0 && output_max.1 > 0 {
+            let size = frame.size().unwrap();
+            let (out_w, out_h) = thumbnail_size(size.width, size.height, output_max.0, output_max.1);
+            if (out_w, out_h) != (size.width, size.height) {
+                let mut resized = Mat::default();
+                match imgproc::resize(&frame, &mut resized, opencv::core::Size::new(out_w, out_h), 0.0, 0.0, imgproc::INTER_AREA) {
+                    Ok(()) => Some(std::mem::replace(&mut frame, resized)),
+                    Err(e) => {
+                        eprintln!("Camera node: Failed to downscale frame, sending full resolution: {}", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        // 将OpenCV Mat转换为字节数组 - BGR格式，或按配置压缩为 JPEG
+        let size = frame.size().unwrap();
+        let channels = frame.channels();
+        
+        let (mat_data, sent_encoding) = match frame_payload(&frame, frame_encoding, jpeg_quality) {
+            Ok(payload) => payload,
+            Err(e) => {
+                eprintln!("Camera node: Failed to read frame data: {}", e);
+                continue;
+            }
+        };
+        
+        // 验证数据大小
+        let actual_width = size.width as i32;
+        let actual_height = size.height as i32;
+        let actual_channels = channels;
+        let calculated_size = (actual_width * actual_height * actual_channels) as usize;
+        
+        println!("Camera node: Frame size: {}, Data length: {}, Calculated: {}x{}x{}={}", 
+                frame_count, mat_data.len(), actual_width, actual_height, actual_channels, calculated_size);
+
+        // 使用正确的API发送数据
+        let output_id = DataId::from("frame".to_string());
+     //   let parameters = MetadataParameters::default();
+        
+        let mut parameters = MetadataParameters::new();
+        parameters.insert("width".to_string(), dora_node_api::Parameter::String(actual_width.to_string()));
+        parameters.insert("height".to_string(), dora_node_api::Parameter::String(actual_height.to_string()));
+        parameters.insert("channels".to_string(), dora_node_api::Parameter::String(actual_channels.to_string()));
+        parameters.insert(ENCODING_PARAM.to_string(), dora_node_api::Parameter::String(sent_encoding.as_str().to_string()));
+        parameters.insert("frame_id".to_string(), dora_node_api::Parameter::String(frame_count.to_string()));
+        // 采集时间戳（Unix毫秒），供下游判断帧的新鲜度
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        parameters.insert("timestamp_ms".to_string(), dora_node_api::Parameter::String(timestamp_ms.to_string()));
+        
+        // 缩小发送时记录采集分辨率，并按需另发原分辨率帧（相同 frame_id 与时间戳）
+        if let Some(full) = &full_frame {
+            parameters.insert("source_width".to_string(), dora_node_api::Parameter::String(full.cols().to_string()));
+            parameters.insert("source_height".to_string(), dora_node_api::Parameter::String(full.rows().to_string()));
+            if fullres_output {
+                let mut full_params = parameters.clone();
+                full_params.insert("width".to_string(), dora_node_api::Parameter::String(full.cols().to_string()));
+                full_params.insert("height".to_string(), dora_node_api::Parameter::String(full.rows().to_string()));
+                match frame_payload(full, frame_encoding, jpeg_quality) {
+                    Ok((full_data, full_encoding)) => {
+                        full_params.insert(ENCODING_PARAM.to_string(), dora_node_api::Parameter::String(full_encoding.as_str().to_string()));
+                        if let Err(e) = node.send_output_bytes(
+                            DataId::from("frame_full".to_string()),
+                            full_params,
+                            full_data.len(),
+                            &full_data,
+                        ) {
+                            eprintln!("Camera node: Failed to send full resolution frame: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Camera node: Failed to read full resolution frame: {}", e),
+                }
+            }
+        }
+
+        // 缩略图与原始帧使用相同的 frame_id 与时间戳
+        if thumbnail_max.0 > 0 && thumbnail_max.1 > 0 {
+            let (thumb_w, thumb_h) = thumbnail_size(actual_width, actual_height, thumbnail_max.0, thumbnail_max.1);
+            match make_thumbnail(&frame, thumb_w, thumb_h) {
+                Ok(thumb_data) => {
+                    // 缩略图始终为原始像素
+                    let mut thumb_params = parameters.clone();
+                    thumb_params.insert(ENCODING_PARAM.to_string(), dora_node_api::Parameter::String(FrameEncoding::Raw.as_str().to_string()));
+                    thumb_params.insert("width".to_string(), dora_node_api::Parameter::String(thumb_w.to_string()));
+                    thumb_params.insert("height".to_string(), dora_node_api::Parameter::String(thumb_h.to_string()));
+                    if let Err(e) = node.send_output_bytes(
+                        DataId::from("thumbnail".to_string()),
+                        thumb_params,
+                        thumb_data.len(),
+                        &thumb_data,
+                    ) {
+                        eprintln!("Camera node: Failed to send thumbnail: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Camera node: Failed to create thumbnail: {}", e),
+            }
+        }
+        
+        match node.send_output_bytes(output_id, parameters, mat_data.len(), &mat_data) {
+            Ok(_) => {
+                frame_count += 1;
+                println!("Camera node: Sent frame {}", frame_count);
+            },
+            Err(e) => {
+                eprintln!("Camera node: Failed to send frame: {}", e);
+                // 继续运行，不退出
+            }
+        }
     }
 
     // 销毁窗口
@@ -163,3 +700,92 @@ fn main() {
 
     println!("Camera node: Finished, sent {} frames total", frame_count);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_spec_distinguishes_files_from_streams() {
+        assert_eq!(SourceSpec::parse(""), SourceSpec::Device(0));
+        assert_eq!(SourceSpec::parse(" 2 "), SourceSpec::Device(2));
+        assert_eq!(SourceSpec::parse("stdin"), SourceSpec::Stdin);
+        assert_eq!(SourceSpec::parse("pipe:/tmp/frames"), SourceSpec::Pipe("/tmp/frames".to_string()));
+        assert_eq!(SourceSpec::parse("videos/demo.mp4"), SourceSpec::File("videos/demo.mp4".to_string()));
+        assert_eq!(SourceSpec::parse("file:///data/demo.mp4"), SourceSpec::File("/data/demo.mp4".to_string()));
+        assert_eq!(SourceSpec::parse("rtsp://10.0.0.2/live"), SourceSpec::Stream("rtsp://10.0.0.2/live".to_string()));
+        assert_eq!(SourceSpec::parse("http://host/stream.mjpg"), SourceSpec::Stream("http://host/stream.mjpg".to_string()));
+        // 路径中间出现 :// 但前缀不是合法 scheme 时仍按文件处理
+        assert_eq!(SourceSpec::parse("/data/a b://c.mp4"), SourceSpec::File("/data/a b://c.mp4".to_string()));
+    }
+
+    #[test]
+    fn next_capture_keeps_fixed_cadence_without_bursting() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+        // 按时采集时沿固定节奏递推
+        assert_eq!(next_capture_due(start, interval, start + Duration::from_millis(5)), start + interval);
+        // 落后一个间隔以上时从当前时刻重新计时，而不是连续补采
+        let late = start + Duration::from_millis(350);
+        assert_eq!(next_capture_due(start, interval, late), late + interval);
+    }
+
+    /// 每次最多返回 chunk 字节的读取器，模拟管道的部分读取
+    struct ChunkedReader {
+        data: std::io::Cursor<Vec<u8>>,
+        chunk: usize,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.chunk);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn raw_reader_assembles_short_reads_and_drops_truncated_tail() {
+        // 2x2 BGR 帧为 12 字节：两个完整帧加 6 字节不完整的尾帧
+        let data: Vec<u8> = (0..30).collect();
+        let reader = ChunkedReader { data: std::io::Cursor::new(data), chunk: 5 };
+        let mut frames = RawFrameReader::new(reader, 2, 2, RawFormat::Bgr);
+        assert_eq!(frames.next_frame().unwrap(), Some((0..12).collect()));
+        assert_eq!(frames.next_frame().unwrap(), Some((12..24).collect()));
+        assert_eq!(frames.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn yuv_matrices_convert_known_pixels() {
+        for matrix in [YuvMatrix::Bt601, YuvMatrix::Bt709] {
+            assert_eq!(matrix.yuv_to_bgr(16, 128, 128), [0, 0, 0]);
+            assert_eq!(matrix.yuv_to_bgr(235, 128, 128), [255, 255, 255]);
+        }
+        // BT.601 的纯红 (Y=81, U=90, V=240)
+        assert_eq!(YuvMatrix::Bt601.yuv_to_bgr(81, 90, 240), [0, 0, 254]);
+        // BT.709 的纯红 (Y=63, U=102, V=240)；用 BT.601 解码会偏暗并带蓝色
+        assert_eq!(YuvMatrix::Bt709.yuv_to_bgr(63, 102, 240), [0, 1, 255]);
+        assert_eq!(YuvMatrix::Bt601.yuv_to_bgr(63, 102, 240), [2, 0, 233]);
+
+        assert_eq!(YuvMatrix::select("", 1080), Ok(YuvMatrix::Bt709));
+        assert_eq!(YuvMatrix::select("auto", 480), Ok(YuvMatrix::Bt601));
+        assert_eq!(YuvMatrix::select("bt601", 1080), Ok(YuvMatrix::Bt601));
+        assert!(YuvMatrix::select("bt2020", 1080).is_err());
+    }
+
+    #[test]
+    fn nv12_shares_chroma_across_2x2_blocks() {
+        let nv12 = vec![16, 235, 235, 16, 128, 128];
+        let bgr = RawFormat::Nv12(YuvMatrix::Bt601).to_bgr(nv12, 2, 2).unwrap();
+        assert_eq!(bgr, vec![0, 0, 0, 255, 255, 255, 255, 255, 255, 0, 0, 0]);
+        assert!(RawFormat::Nv12(YuvMatrix::Bt601).to_bgr(vec![0; 9], 3, 2).is_err());
+    }
+
+    #[test]
+    fn thumbnail_keeps_aspect_ratio_without_upscaling() {
+        assert_eq!(thumbnail_size(1920, 1080, 320, 320), (320, 180));
+        assert_eq!(thumbnail_size(1080, 1920, 320, 320), (180, 320));
+        assert_eq!(thumbnail_size(100, 50, 320, 320), (100, 50));
+        // 极端宽高比时短边至少 1 像素
+        assert_eq!(thumbnail_size(10000, 10, 100, 100), (100, 1));
+    }
+}
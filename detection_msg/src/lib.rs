@@ -0,0 +1,462 @@
+//! 检测结果的消息格式，检测节点与可视化节点共用
+//!
+//! 每帧的检测结果编码为一个 Arrow `StructArray`，每行一条检测，列为：
+//! - `name`、`class_name`：utf8
+//! - `confidence`、`x`、`y`、`width`、`height`：f32（归一化中心点坐标与宽高）
+//! - `extensions`：可为空的 binary，可选扩展字段，编码为 1 字节扩展数量和若干扩展，
+//!   每个扩展为 `[tag(1字节), length(2字节小端), value(length字节)]`。
+//!   解析时保留未知 tag，新增能力（跟踪、姿态、分割等）不会破坏旧的消费者
+//!
+//! 命令行子命令另用纯文本格式读写检测结果，每行为 `class conf x y w h`
+
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BinaryArray, Float32Array, StringArray, StructArray};
+use arrow::datatypes::{DataType, Field, Fields};
+/// 扩展 tag
+pub const TAG_TRACK_ID: u8 = 1;
+pub const TAG_ANGLE: u8 = 2;
+pub const TAG_KEYPOINTS: u8 = 3;
+
+/// 一个关键点：归一化坐标与置信度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keypoint {
+    pub x: f32,
+    pub y: f32,
+    pub confidence: f32,
+}
+
+/// 可选扩展字段
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extension {
+    /// 跟踪ID（u64）
+    TrackId(u64),
+    /// 旋转框角度（弧度，f32）
+    Angle(f32),
+    /// 姿态关键点，每个 12 字节
+    Keypoints(Vec<Keypoint>),
+    /// 本版本不认识的扩展，原样保留以便转发
+    Unknown { tag: u8, data: Vec<u8> },
+}
+
+impl Extension {
+    fn tag(&self) -> u8 {
+        match self {
+            Extension::TrackId(_) => TAG_TRACK_ID,
+            Extension::Angle(_) => TAG_ANGLE,
+            Extension::Keypoints(_) => TAG_KEYPOINTS,
+            Extension::Unknown { tag, .. } => *tag,
+        }
+    }
+
+    fn value_bytes(&self) -> Vec<u8> {
+        match self {
+            Extension::TrackId(id) => id.to_le_bytes().to_vec(),
+            Extension::Angle(angle) => angle.to_le_bytes().to_vec(),
+            Extension::Keypoints(points) => points
+                .iter()
+                .flat_map(|p| [p.x, p.y, p.confidence])
+                .flat_map(f32::to_le_bytes)
+                .collect(),
+            Extension::Unknown { data, .. } => data.clone(),
+        }
+    }
+
+    fn from_tag(tag: u8, data: &[u8]) -> Result<Self, DecodeError> {
+        let invalid = || DecodeError::InvalidExtension { tag, len: data.len() };
+        Ok(match tag {
+            TAG_TRACK_ID => Extension::TrackId(u64::from_le_bytes(data.try_into().map_err(|_| invalid())?)),
+            TAG_ANGLE => Extension::Angle(f32::from_le_bytes(data.try_into().map_err(|_| invalid())?)),
+            TAG_KEYPOINTS => {
+                if !data.len().is_multiple_of(12) {
+                    return Err(invalid());
+                }
+                Extension::Keypoints(
+                    data.chunks_exact(12)
+                        .map(|c| Keypoint { x: read_f32(c, 0), y: read_f32(c, 4), confidence: read_f32(c, 8) })
+                        .collect(),
+                )
+            }
+            _ => Extension::Unknown { tag, data: data.to_vec() },
+        })
+    }
+}
+
+/// 一条检测记录：基础字段加可选扩展
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DetectionRecord {
+    pub name: String,
+    pub class_name: String,
+    pub confidence: f32,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub extensions: Vec<Extension>,
+}
+
+impl DetectionRecord {
+    /// 定长模式的哨兵记录：名称与类别为空、置信度为0，下游应跳过
+    pub fn is_sentinel(&self) -> bool {
+        self.class_name.is_empty() && self.confidence == 0.0
+    }
+
+    pub fn track_id(&self) -> Option<u64> {
+        self.extensions.iter().find_map(|e| match e {
+            Extension::TrackId(id) => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// 设置跟踪ID，替换已有的跟踪ID扩展
+    pub fn set_track_id(&mut self, id: u64) {
+        self.extensions.retain(|e| !matches!(e, Extension::TrackId(_)));
+        self.extensions.push(Extension::TrackId(id));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// 数组类型或列与约定的结构不符
+    Schema(String),
+    /// 扩展数据在中途截断
+    Truncated { offset: usize },
+    /// 已知 tag 的扩展长度不符
+    InvalidExtension { tag: u8, len: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Schema(message) => write!(f, "unexpected detection schema: {}", message),
+            DecodeError::Truncated { offset } => write!(f, "extension data truncated at byte {}", offset),
+            DecodeError::InvalidExtension { tag, len } => write!(f, "invalid length {} for extension tag {}", len, tag),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 文本格式检测结果的解析错误，line 从 1 开始
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// 检测结果 `StructArray` 的列定义
+pub fn fields() -> Fields {
+    Fields::from(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("class_name", DataType::Utf8, false),
+        Field::new("confidence", DataType::Float32, false),
+        Field::new("x", DataType::Float32, false),
+        Field::new("y", DataType::Float32, false),
+        Field::new("width", DataType::Float32, false),
+        Field::new("height", DataType::Float32, false),
+        Field::new("extensions", DataType::Binary, true),
+    ])
+}
+
+/// 编码为 `StructArray`；没有扩展的记录在 `extensions` 列为 null
+pub fn to_arrow(records: &[DetectionRecord]) -> ArrayRef {
+    let f32_column = |value: fn(&DetectionRecord) -> f32| -> ArrayRef {
+        Arc::new(records.iter().map(value).collect::<Float32Array>())
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(records.iter().map(|r| Some(r.name.as_str())).collect::<StringArray>()),
+        Arc::new(records.iter().map(|r| Some(r.class_name.as_str())).collect::<StringArray>()),
+        f32_column(|r| r.confidence),
+        f32_column(|r| r.x),
+        f32_column(|r| r.y),
+        f32_column(|r| r.width),
+        f32_column(|r| r.height),
+        Arc::new(
+            records
+                .iter()
+                .map(|r| (!r.extensions.is_empty()).then(|| encode_extensions(&r.extensions)))
+                .collect::<BinaryArray>(),
+        ),
+    ];
+    Arc::new(StructArray::new(fields(), columns, None))
+}
+
+/// 从 `StructArray` 解码；类型或列不符时返回 `DecodeError::Schema`
+pub fn from_arrow(array: &dyn Array) -> Result<Vec<DetectionRecord>, DecodeError> {
+    let array = array
+        .as_any()
+        .downcast_ref::<StructArray>()
+        .ok_or_else(|| DecodeError::Schema(format!("expected a struct array, got {}", array.data_type())))?;
+    let name = column::<StringArray>(array, "name")?;
+    let class_name = column::<StringArray>(array, "class_name")?;
+    let confidence = column::<Float32Array>(array, "confidence")?;
+    let x = column::<Float32Array>(array, "x")?;
+    let y = column::<Float32Array>(array, "y")?;
+    let width = column::<Float32Array>(array, "width")?;
+    let height = column::<Float32Array>(array, "height")?;
+    // 扩展列可选，缺少时视为没有扩展
+    let extensions = match array.column_by_name("extensions") {
+        Some(_) => Some(column::<BinaryArray>(array, "extensions")?),
+        None => None,
+    };
+
+    (0..array.len())
+        .map(|i| {
+            Ok(DetectionRecord {
+                name: name.value(i).to_string(),
+                class_name: class_name.value(i).to_string(),
+                confidence: confidence.value(i),
+                x: x.value(i),
+                y: y.value(i),
+                width: width.value(i),
+                height: height.value(i),
+                extensions: match extensions {
+                    Some(column) if column.is_valid(i) => decode_extensions(column.value(i))?,
+                    _ => Vec::new(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// 文本格式的一行：`class conf x y w h`，数值保留 4 位小数，不含扩展
+pub fn to_text_line(record: &DetectionRecord) -> String {
+    format!(
+        "{} {:.4} {:.4} {:.4} {:.4} {:.4}",
+        record.class_name, record.confidence, record.x, record.y, record.width, record.height
+    )
+}
+
+/// 解析 `to_text_line` 写出的多行文本（类别名可含空格，取每行最后五个字段为数值），忽略空行
+pub fn from_text_lines(text: &str) -> Result<Vec<DetectionRecord>, ParseError> {
+    text.lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(idx, line)| {
+            let error = |message: String| ParseError { line: idx + 1, message };
+            let mut fields = line.rsplitn(6, char::is_whitespace);
+            let mut values = [0.0f32; 5];
+            for value in values.iter_mut().rev() {
+                let field = fields.next().ok_or_else(|| error("expected `class conf x y w h`".to_string()))?;
+                *value = field.parse().map_err(|_| error(format!("'{}' is not a number", field)))?;
+            }
+            let class_name = fields
+                .next()
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .ok_or_else(|| error("missing class name".to_string()))?;
+            Ok(DetectionRecord {
+                name: format!("{}_{}", class_name, idx),
+                class_name: class_name.to_string(),
+                confidence: values[0],
+                x: values[1],
+                y: values[2],
+                width: values[3],
+                height: values[4],
+                extensions: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+fn column<'a, T: Array + 'static>(array: &'a StructArray, name: &str) -> Result<&'a T, DecodeError> {
+    let column = array
+        .column_by_name(name)
+        .ok_or_else(|| DecodeError::Schema(format!("missing column '{}'", name)))?;
+    column
+        .as_any()
+        .downcast_ref::<T>()
+        .ok_or_else(|| DecodeError::Schema(format!("column '{}' has unexpected type {}", name, column.data_type())))
+}
+
+/// 编码扩展字段；最多 255 个扩展，单个扩展最长 65535 字节，超出部分被丢弃
+fn encode_extensions(extensions: &[Extension]) -> Vec<u8> {
+    let extensions: Vec<(u8, Vec<u8>)> = extensions
+        .iter()
+        .map(|e| (e.tag(), e.value_bytes()))
+        .filter(|(_, value)| value.len() <= u16::MAX as usize)
+        .take(u8::MAX as usize)
+        .collect();
+    let mut bytes = vec![extensions.len() as u8];
+    for (tag, value) in extensions {
+        bytes.push(tag);
+        bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&value);
+    }
+    bytes
+}
+
+fn decode_extensions(bytes: &[u8]) -> Result<Vec<Extension>, DecodeError> {
+    let count = *bytes.first().ok_or(DecodeError::Truncated { offset: 0 })?;
+    let mut offset = 1;
+    let mut extensions = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let header = bytes.get(offset..offset + 3).ok_or(DecodeError::Truncated { offset })?;
+        let tag = header[0];
+        let len = u16::from_le_bytes([header[1], header[2]]) as usize;
+        offset += 3;
+        let value = bytes.get(offset..offset + len).ok_or(DecodeError::Truncated { offset })?;
+        extensions.push(Extension::from_tag(tag, value)?);
+        offset += len;
+    }
+    Ok(extensions)
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, UInt8Array};
+
+    fn record(class_name: &str, confidence: f32) -> DetectionRecord {
+        DetectionRecord {
+            name: format!("{}_0", class_name),
+            class_name: class_name.to_string(),
+            confidence,
+            x: 0.25,
+            y: 0.5,
+            width: 0.1,
+            height: 0.2,
+            extensions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn round_trips_records() {
+        let records = vec![record("person", 0.9), record("traffic light", 0.4), DetectionRecord::default()];
+        let array = to_arrow(&records);
+        assert_eq!(array.len(), 3);
+        assert_eq!(from_arrow(array.as_ref()).unwrap(), records);
+        assert!(from_arrow(array.as_ref()).unwrap()[2].is_sentinel());
+    }
+
+    #[test]
+    fn round_trips_empty_frame() {
+        let array = to_arrow(&[]);
+        assert_eq!(from_arrow(array.as_ref()).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rejects_non_struct_array() {
+        let array = UInt8Array::from(vec![1u8, 2, 3]);
+        assert!(matches!(from_arrow(&array), Err(DecodeError::Schema(_))));
+    }
+
+    #[test]
+    fn rejects_wrong_column_type() {
+        let mut columns: Vec<ArrayRef> = to_arrow(&[record("car", 0.5)])
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap()
+            .columns()
+            .to_vec();
+        columns[2] = Arc::new(Int32Array::from(vec![1]));
+        let mut field_list: Vec<Field> = fields().iter().map(|f| f.as_ref().clone()).collect();
+        field_list[2] = Field::new("confidence", DataType::Int32, false);
+        let array = StructArray::new(Fields::from(field_list), columns, None);
+        assert!(matches!(from_arrow(&array), Err(DecodeError::Schema(_))));
+    }
+
+    #[test]
+    fn extensions_column_is_optional() {
+        let full = to_arrow(&[record("dog", 0.7)]);
+        let full = full.as_any().downcast_ref::<StructArray>().unwrap();
+        let field_list: Vec<Field> = fields().iter().take(7).map(|f| f.as_ref().clone()).collect();
+        let array = StructArray::new(Fields::from(field_list), full.columns()[..7].to_vec(), None);
+        assert_eq!(from_arrow(&array).unwrap(), vec![record("dog", 0.7)]);
+    }
+
+    #[test]
+    fn round_trips_extensions() {
+        let mut with_extensions = record("person", 0.8);
+        with_extensions.extensions = vec![
+            Extension::TrackId(42),
+            Extension::Angle(0.5),
+            Extension::Keypoints(vec![
+                Keypoint { x: 0.1, y: 0.2, confidence: 0.9 },
+                Keypoint { x: 0.3, y: 0.4, confidence: 0.5 },
+            ]),
+            Extension::Unknown { tag: 200, data: vec![1, 2, 3] },
+        ];
+        let records = vec![with_extensions, record("car", 0.6)];
+        let decoded = from_arrow(to_arrow(&records).as_ref()).unwrap();
+        assert_eq!(decoded, records);
+        assert_eq!(decoded[0].track_id(), Some(42));
+        assert_eq!(decoded[1].track_id(), None);
+    }
+
+    #[test]
+    fn preserves_unknown_tags() {
+        // 2 个扩展：未知 tag 9（2字节）和跟踪ID
+        let mut bytes = vec![2, 9, 2, 0, 0xAB, 0xCD, TAG_TRACK_ID, 8, 0];
+        bytes.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(
+            decode_extensions(&bytes).unwrap(),
+            vec![Extension::Unknown { tag: 9, data: vec![0xAB, 0xCD] }, Extension::TrackId(7)]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_extensions() {
+        assert_eq!(decode_extensions(&[]), Err(DecodeError::Truncated { offset: 0 }));
+        assert_eq!(decode_extensions(&[1, TAG_ANGLE, 4]), Err(DecodeError::Truncated { offset: 1 }));
+        assert_eq!(decode_extensions(&[1, TAG_ANGLE, 4, 0, 0, 0]), Err(DecodeError::Truncated { offset: 4 }));
+    }
+
+    #[test]
+    fn rejects_invalid_known_extension_length() {
+        assert_eq!(
+            decode_extensions(&[1, TAG_TRACK_ID, 2, 0, 1, 2]),
+            Err(DecodeError::InvalidExtension { tag: TAG_TRACK_ID, len: 2 })
+        );
+        assert_eq!(
+            decode_extensions(&[1, TAG_KEYPOINTS, 4, 0, 0, 0, 0, 0]),
+            Err(DecodeError::InvalidExtension { tag: TAG_KEYPOINTS, len: 4 })
+        );
+    }
+
+    #[test]
+    fn set_track_id_replaces_existing_id() {
+        let mut record = record("person", 0.9);
+        record.extensions.push(Extension::Angle(1.0));
+        record.set_track_id(3);
+        record.set_track_id(5);
+        assert_eq!(record.track_id(), Some(5));
+        assert_eq!(record.extensions, vec![Extension::Angle(1.0), Extension::TrackId(5)]);
+    }
+
+    #[test]
+    fn text_lines_round_trip_with_spaced_class_names() {
+        let records = [record("person", 0.9), record("traffic light", 0.4)];
+        let text: String = records.iter().map(|r| to_text_line(r) + "\n").collect();
+        assert_eq!(text.lines().next(), Some("person 0.9000 0.2500 0.5000 0.1000 0.2000"));
+
+        let parsed = from_text_lines(&format!("{}\n\n", text)).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].class_name, "traffic light");
+        assert_eq!(parsed[1].confidence, 0.4);
+        assert_eq!((parsed[1].x, parsed[1].y, parsed[1].width, parsed[1].height), (0.25, 0.5, 0.1, 0.2));
+    }
+
+    #[test]
+    fn text_lines_report_failing_line() {
+        let error = from_text_lines("person 0.9 0.3 0.4 0.2 0.4\nperson 0.9 0.3 abc 0.2 0.4").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.to_string(), "Line 2: 'abc' is not a number");
+        assert_eq!(from_text_lines("0.9 0.3 0.4").unwrap_err().message, "expected `class conf x y w h`");
+        assert_eq!(from_text_lines("0.9 0.3 0.4 0.2 0.4").unwrap_err().message, "missing class name");
+    }
+}
@@ -1,35 +1,118 @@
 use dora_node_api::{DoraNode, Event, dora_core::config::DataId, MetadataParameters};
-use dora_node_api::arrow::array::{UInt8Array, Array as ArrowArray};
-use std::time::Duration;
+use dora_node_api::arrow::array::{UInt8Array, Float32Array, Array as ArrowArray, ArrayRef};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::path::Path;
 use tract_onnx::prelude::*;
-use opencv::{core::{Mat}, imgproc, prelude::*};
+use opencv::{core::{Mat, UMat}, imgproc, prelude::*};
 use anyhow::{Result, Context};
+use clap::{Parser, Subcommand};
+use detection_msg::DetectionRecord;
+use frame_utils::copy_into_mat;
+use node_utils::{ClassGroups, FrameEncoding, FrameGapTracker, FrameWatchdog, OverflowPolicy, PushOutcome, SharedQueue, ENCODING_PARAM};
 
-#[derive(Debug, Clone)]
-struct Detection {
-    name: String,          // 检测对象的唯一标识名
-    class_name: String,    // 类别名称（如"person", "car"等）
-    confidence: f32,       // 置信度
-    x: f32,                // 归一化中心x坐标
-    y: f32,                // 归一化中心y坐标
-    width: f32,            // 归一化宽度
-    height: f32,           // 归一化高度
-}
+type YoloModel = RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
 
-struct YoloDetector {
-    model: Option<RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>>,
+// 一个已加载的模型及其输入尺寸
+struct ModelVariant {
+    model: YoloModel,
     input_width: usize,
     input_height: usize,
+    accepts_text: bool,  // 模型是否带有文本嵌入输入（YOLO-World）
+}
+
+// YOLO-World 开放词汇提示词及其预先计算好的文本嵌入
+struct TextPrompts {
+    prompts: Vec<String>,
+    embeddings: Vec<f32>,  // prompts.len() x dim，按行存储
+    dim: usize,
+}
+
+impl TextPrompts {
+    /// 从 YOLO_WORLD_PROMPTS（逗号分隔）和 YOLO_WORLD_EMBEDDINGS（小端 f32 原始文件）加载
+    fn from_env() -> Result<Option<Self>> {
+        let prompts: Vec<String> = match std::env::var("YOLO_WORLD_PROMPTS") {
+            Ok(value) => value.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect(),
+            Err(_) => return Ok(None),
+        };
+        if prompts.is_empty() {
+            return Ok(None);
+        }
+        let path = std::env::var("YOLO_WORLD_EMBEDDINGS")
+            .context("YOLO_WORLD_PROMPTS is set but YOLO_WORLD_EMBEDDINGS is missing")?;
+        let bytes = std::fs::read(&path)
+            .with_context(|| format!("Failed to read text embeddings {}", path))?;
+        let embeddings: Vec<f32> = bytes.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Self::new(prompts, embeddings).map(Some)
+    }
+    
+    fn new(prompts: Vec<String>, embeddings: Vec<f32>) -> Result<Self> {
+        if prompts.is_empty() || embeddings.is_empty() || embeddings.len() % prompts.len() != 0 {
+            anyhow::bail!(
+                "Text embeddings ({} values) cannot be split evenly across {} prompts",
+                embeddings.len(), prompts.len()
+            );
+        }
+        let dim = embeddings.len() / prompts.len();
+        Ok(Self { prompts, embeddings, dim })
+    }
+    
+    /// 文本输入张量，形状为 [1, 提示词数量, 嵌入维度]
+    fn to_tensor(&self) -> Result<Tensor> {
+        let array = tract_core::ndarray::ArrayD::<f32>::from_shape_vec(
+            vec![1, self.prompts.len(), self.dim],
+            self.embeddings.clone(),
+        ).context("Failed to create text embedding array")?;
+        Ok(Tensor::from(array))
+    }
+}
+
+/// 核对提示词嵌入与模型文本输入声明的形状：声明为具体形状时须为 [1, 提示词数量, 嵌入维度]，
+/// 动态形状（declared 为 None）时由提示词决定
+fn check_text_input_shape(declared: Option<&[usize]>, num_prompts: usize, dim: usize) -> Result<()> {
+    match declared {
+        Some(shape) if shape != [1, num_prompts, dim] => anyhow::bail!(
+            "Model text input expects shape {:?}, but {} prompts with {}-dim embeddings give [1, {}, {}]",
+            shape, num_prompts, dim, num_prompts, dim
+        ),
+        _ => Ok(()),
+    }
+}
+
+struct YoloDetector {
+    model: Option<ModelVariant>,
+    fast_model: Option<ModelVariant>,  // 可选的小输入尺寸模型，负载高时切换使用
+    use_fast_model: bool,
     class_names: Vec<String>,
+    text_input: Option<Tensor>,  // YOLO-World 文本嵌入输入，仅对带文本输入的模型生效
+    use_opencl: bool,            // 预处理是否走 UMat/OpenCL 加速路径
+    nms: NmsConfig,
+    conf_threshold: f32,         // 候选框的最低类别置信度，可经 config 输入运行时调整
+    class_thresholds: std::collections::HashMap<String, f32>,  // 按类别覆盖的置信度阈值
+    preprocess_pipeline: PreprocessPipeline,
+    postprocessors: Vec<Box<dyn PostProcessor>>,
+    forced_postprocessor: Option<String>,  // YOLO_POSTPROCESS 指定的变体，None 时按输出形状自动选择
+    // 跨帧复用的输入张量：推理结束后只剩这里持有引用，下一帧原地写入
+    input_buffer: std::sync::Mutex<Option<std::sync::Arc<Tensor>>>,
 }
 
 impl YoloDetector {
     fn new(model_path: &str) -> Result<Self> {
         eprintln!("Initializing YOLO detector with model: {}", model_path);
         
+        // 可选的 YOLO-World 文本提示词
+        let text_prompts = match TextPrompts::from_env() {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("Failed to load YOLO-World prompts, ignoring them: {:#}", e);
+                None
+            }
+        };
+        let text_shape = text_prompts.as_ref().map(|p| (p.prompts.len(), p.dim));
+        
         let model = if Path::new(model_path).exists() {
-            match Self::load_model(model_path) {
+            match Self::load_model(model_path, 640, text_shape) {
                 Ok(m) => Some(m),
                 Err(e) => {
                     eprintln!("Failed to load model: {}", e);
@@ -42,7 +125,7 @@ impl YoloDetector {
         };
         
         // COCO类别名称
-        let class_names = vec![
+        let mut class_names: Vec<String> = vec![
             "person", "bicycle", "car", "motorcycle", "airplane", "bus", "train", "truck", "boat",
             "traffic light", "fire hydrant", "stop sign", "parking meter", "bench", "bird", "cat",
             "dog", "horse", "sheep", "cow", "elephant", "bear", "zebra", "giraffe", "backpack",
@@ -54,35 +137,207 @@ impl YoloDetector {
             "remote", "keyboard", "cell phone", "microwave", "oven", "toaster", "sink", "refrigerator",
             "book", "clock", "vase", "scissors", "teddy bear", "hair drier", "toothbrush"
         ].iter().map(|&s| s.to_string()).collect();
+        // 自定义类别名文件（每行一个），读取失败时沿用 COCO 类别
+        if let Ok(names_path) = std::env::var("YOLO_CLASS_NAMES") {
+            match load_class_names(&names_path) {
+                Ok(names) => {
+                    eprintln!("Loaded {} class names from {}", names.len(), names_path);
+                    class_names = names;
+                }
+                Err(e) => eprintln!("Failed to load class names from {}, using COCO classes: {:#}", names_path, e),
+            }
+        }
+        
+        // 可选的快速模型（如416输入），用于按负载切换分辨率
+        let fast_model = match std::env::var("YOLO_FAST_MODEL_PATH") {
+            Ok(fast_path) if Path::new(&fast_path).exists() => {
+                let fast_size: usize = env_or("YOLO_FAST_INPUT_SIZE", 416);
+                match Self::load_model(&fast_path, fast_size, text_shape) {
+                    Ok(m) => Some(m),
+                    Err(e) => {
+                        eprintln!("Failed to load fast model: {}", e);
+                        None
+                    }
+                }
+            }
+            Ok(fast_path) => {
+                eprintln!("Fast model file not found at {}. Resolution switching disabled.", fast_path);
+                None
+            }
+            Err(_) => None,
+        };
+        
+        // 提示词即类别名；模型没有文本输入时回退到固定类别
+        let text_input = match text_prompts {
+            Some(prompts) if model.as_ref().map_or(false, |m| m.accepts_text) => {
+                eprintln!("Using {} YOLO-World prompts as classes: {:?}", prompts.prompts.len(), prompts.prompts);
+                let tensor = prompts.to_tensor()?;
+                class_names = prompts.prompts;
+                Some(tensor)
+            }
+            Some(_) => {
+                eprintln!("Model has no text input; ignoring YOLO-World prompts and using fixed classes");
+                None
+            }
+            None => None,
+        };
         
-        eprintln!("YOLO detector created. Model loaded: {}", model.is_some());
+        // 可选的 OpenCL 预处理加速，不可用时回退到 Mat
+        let use_opencl = env_or("YOLO_USE_OPENCL", false) && enable_opencl();
         
-        Ok(Self {
+        eprintln!("YOLO detector created. Model loaded: {}, fast model loaded: {}", model.is_some(), fast_model.is_some());
+        
+        let mut detector = Self {
             model,
-            input_width: 640,
-            input_height: 640,
+            fast_model,
+            use_fast_model: false,
             class_names,
-        })
+            text_input,
+            use_opencl,
+            nms: NmsConfig::from_env(),
+            conf_threshold: env_unit_interval("YOLO_CONF_THRESHOLD", 0.1),
+            class_thresholds: class_thresholds_from_env(),
+            preprocess_pipeline: PreprocessPipeline::from_env(),
+            postprocessors: default_postprocessors(),
+            forced_postprocessor: None,
+            input_buffer: std::sync::Mutex::new(None),
+        };
+        let variant_name: String = env_or("YOLO_POSTPROCESS", "auto".to_string());
+        if variant_name != "auto" {
+            if detector.postprocessors.iter().any(|p| p.name() == variant_name) {
+                detector.forced_postprocessor = Some(variant_name);
+            } else {
+                let known: Vec<&str> = detector.postprocessors.iter().map(|p| p.name()).collect();
+                eprintln!("Unknown YOLO_POSTPROCESS '{}', expected auto or one of {:?}; using auto", variant_name, known);
+            }
+        }
+        detector.check_class_count(env_or("YOLO_FIX_CLASS_NAMES", false));
+        Ok(detector)
+    }
+    
+    /// 用全零输入跑一次推理
+    fn run_dummy(&self, variant: &ModelVariant) -> TractResult<TVec<TValue>> {
+        let input = Tensor::zero::<f32>(&[1, 3, variant.input_height, variant.input_width])?;
+        let inputs = match (&self.text_input, variant.accepts_text) {
+            (Some(text), true) => tvec!(input.into(), text.clone().into()),
+            _ => tvec!(input.into()),
+        };
+        variant.model.run(inputs)
+    }
+    
+    /// 空闲保温：对当前生效的模型做一次丢弃结果的推理
+    fn warm_up(&self) -> Result<()> {
+        if let Some(variant) = self.active_model() {
+            self.run_dummy(variant).context("Keep-alive inference failed")?;
+        }
+        Ok(())
+    }
+    
+    /// 启动诊断：用一次空输入推理得到输出通道数，推算模型的类别数并与类别名列表比对，
+    /// 不一致时给出明确提示；fix 为 true 时截断或补齐类别名列表
+    fn check_class_count(&mut self, fix: bool) {
+        let Some(variant) = self.model.as_ref() else {
+            return;
+        };
+        let outputs = match self.run_dummy(variant) {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                eprintln!("Class count diagnostic: dummy inference failed: {}", e);
+                return;
+            }
+        };
+        
+        let shapes: Vec<&[usize]> = outputs.iter().map(|o| o.shape()).collect();
+        // 类别数由匹配的后处理实现按各自的输出布局推算（v8 为通道数-4，v5/v7 为通道数-5）
+        let Some((processor, implied_classes)) = self.select_postprocessor(&shapes)
+            .and_then(|p| p.class_count(&shapes).map(|count| (p.name(), count))) else {
+            eprintln!("Class count diagnostic: cannot infer class count from output shapes {:?}", shapes);
+            return;
+        };
+        let shapes = format!("{:?}", shapes);
+        if let Some(message) = reconcile_class_names(&mut self.class_names, implied_classes, fix) {
+            eprintln!("Class count diagnostic: output {} ({} postprocessor): {}", shapes, processor, message);
+        } else {
+            eprintln!("Class count diagnostic: output {} ({} postprocessor) matches {} class names", shapes, processor, implied_classes);
+        }
     }
     
-    fn load_model(model_path: &str) -> Result<RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>> {
+    /// 加载模型；text_shape 为 (提示词数量, 嵌入维度)，仅当模型存在第二个输入时用于设置文本输入形状
+    fn load_model(model_path: &str, fallback_size: usize, text_shape: Option<(usize, usize)>) -> Result<ModelVariant> {
         eprintln!("Loading ONNX model from: {}", model_path);
         
-        let model = tract_onnx::onnx()
+        // 先按模型自身声明的输入形状加载；输入尺寸是动态的时再固定为 fallback_size
+        let (model, accepts_text) = match Self::build_runnable(model_path, None, text_shape) {
+            Ok((model, accepts_text)) if concrete_input_size(&model).is_some() => (model, accepts_text),
+            _ => {
+                eprintln!("Model input size is dynamic, using {}x{}", fallback_size, fallback_size);
+                Self::build_runnable(model_path, Some(fallback_size), text_shape)?
+            }
+        };
+        let (input_width, input_height) = concrete_input_size(&model)
+            .context("Failed to read model input size")?;
+            
+        eprintln!("Successfully loaded and optimized ONNX model (input {}x{})", input_width, input_height);
+        Ok(ModelVariant {
+            model,
+            input_width,
+            input_height,
+            accepts_text,
+        })
+    }
+    
+    /// 加载并优化模型；input_size 为 None 时不覆盖模型声明的图像输入形状
+    fn build_runnable(model_path: &str, input_size: Option<usize>, text_shape: Option<(usize, usize)>) -> Result<(YoloModel, bool)> {
+        let mut model = tract_onnx::onnx()
             .model_for_path(model_path)
-            .context("Failed to load ONNX model")?
-            .with_input_fact(0, InferenceFact::dt_shape(f32::datum_type(), tvec!(1, 3, 640, 640)))
-            .context("Failed to set input fact")?
+            .context("Failed to load ONNX model")?;
+        if let Some(size) = input_size {
+            model = model
+                .with_input_fact(0, InferenceFact::dt_shape(f32::datum_type(), tvec!(1, 3, size, size)))
+                .context("Failed to set input fact")?;
+        }
+        
+        let accepts_text = model.inputs.len() > 1 && text_shape.is_some();
+        if let (true, Some((num_prompts, dim))) = (accepts_text, text_shape) {
+            let declared = model.input_fact(1)
+                .context("Failed to read text input fact")?
+                .shape.as_concrete_finite()?;
+            check_text_input_shape(declared.as_deref(), num_prompts, dim)?;
+            model = model
+                .with_input_fact(1, InferenceFact::dt_shape(f32::datum_type(), tvec!(1, num_prompts, dim)))
+                .context("Failed to set text input fact")?;
+        }
+        
+        let model = model
             .into_optimized()
             .context("Failed to optimize model")?
             .into_runnable()
             .context("Failed to make model runnable")?;
-            
-        eprintln!("Successfully loaded and optimized ONNX model");
-        Ok(model)
+        Ok((model, accepts_text))
+    }
+    
+    /// 是否具备在两个分辨率之间切换的条件
+    fn can_switch_resolution(&self) -> bool {
+        self.model.is_some() && self.fast_model.is_some()
+    }
+    
+    /// 切换到快速（小分辨率）模型或精确（大分辨率）模型
+    fn set_fast_mode(&mut self, fast: bool) {
+        if self.fast_model.is_some() {
+            self.use_fast_model = fast;
+        }
+    }
+    
+    /// 当前生效的模型
+    fn active_model(&self) -> Option<&ModelVariant> {
+        if self.use_fast_model {
+            self.fast_model.as_ref().or(self.model.as_ref())
+        } else {
+            self.model.as_ref()
+        }
     }
     
-    fn preprocess(&self, img_data: &[u8], width: u32, height: u32) -> Result<Tensor> {
+    fn preprocess(&self, img_data: &[u8], width: u32, height: u32, input_width: usize, input_height: usize) -> Result<(TValue, ImageTransform)> {
         eprintln!("Preprocessing image: {}x{}", width, height);
         
         // 创建一个空的 Mat
@@ -91,81 +346,245 @@ impl YoloDetector {
                 .context("Failed to create Mat")?
         };
         
-        // 手动复制数据到 Mat 中
-        unsafe {
-            let data_ptr = img_data.as_ptr() as *const u8;
-            let mat_data = mat.data_mut() as *mut u8;
-            std::ptr::copy_nonoverlapping(data_ptr, mat_data, img_data.len());
-        }
-        
-        // 转换BGR到RGB
-        let mut rgb_mat = Mat::default();
-        imgproc::cvt_color(&mat, &mut rgb_mat, imgproc::COLOR_BGR2RGB, 0)
-            .context("Failed to convert color space")?;
-        
-        // 调整大小
-        let mut resized = Mat::default();
-        let target_size = opencv::core::Size::new(self.input_width as i32, self.input_height as i32);
-        imgproc::resize(
-            &rgb_mat, 
-            &mut resized, 
-            target_size,
-            0.0, 
-            0.0, 
-            imgproc::INTER_LINEAR
-        ).context("Failed to resize image")?;
-        
-        // 归一化到[0,1]范围
-        let mut normalized = Mat::default();
-        resized.convert_to(&mut normalized, opencv::core::CV_32F, 1.0/255.0, 0.0)
-            .context("Failed to normalize image")?;
-        
-        // 将OpenCV Mat转换为tract tensor
-        let mut tensor_data = vec![0.0f32; self.input_width * self.input_height * 3];
-        let mut idx = 0;
-        
-        for y in 0..self.input_height {
-            for x in 0..self.input_width {
-                let mut pixel_values = [0.0f32; 3];
-                let result = normalized.at_2d::<opencv::core::Vec3f>(y as i32, x as i32);
-                if let Ok(pixel) = result {
-                    pixel_values[0] = pixel[0];  // R
-                    pixel_values[1] = pixel[1];  // G
-                    pixel_values[2] = pixel[2];  // B
-                }
-                
-                for c in 0..3 {
-                    tensor_data[idx] = pixel_values[c];
-                    idx += 1;
-                }
-            }
-        }
-        
-        // 重排维度: HWC -> CHW
-        let hwc_array = ndarray::Array::from_shape_vec(
-            (self.input_height, self.input_width, 3), 
-            tensor_data
-        ).context("Failed to create HWC array")?;
+        // 复制数据到 Mat 中（先校验长度，防止元数据与实际数据不符时越界写入）
+        copy_into_mat(&mut mat, img_data)?;
         
-        let chw_array = hwc_array.permuted_axes([2, 0, 1]);
-        let final_array = chw_array.insert_axis(ndarray::Axis(0)); // 添加batch维度
+        // 按配置的步骤执行图像操作（OpenCL 可用时在 UMat 上完成，只下载最终结果）
+        let target_size = opencv::core::Size::new(input_width as i32, input_height as i32);
+        let (processed, transform) = if self.use_opencl {
+            let mut src = UMat::new_def();
+            mat.copy_to(&mut src).context("Failed to upload image to UMat")?;
+            let (processed_gpu, transform) = self.preprocess_pipeline.run_image_steps(src, UMat::new_def, target_size)?;
+            let mut processed = Mat::default();
+            processed_gpu.copy_to(&mut processed).context("Failed to download UMat")?;
+            (processed, transform)
+        } else {
+            self.preprocess_pipeline.run_image_steps(mat, Mat::default, target_size)?
+        };
         
+        let tensor = self.fill_input_buffer(&processed, input_width, input_height)?;
         eprintln!("Preprocessing completed successfully");
+        Ok((tensor, transform))
+    }
+    
+    /// 将预处理结果写入复用的输入张量；形状改变（切换模型）或上一帧的张量仍被占用时重新分配
+    fn fill_input_buffer(&self, processed: &Mat, input_width: usize, input_height: usize) -> Result<TValue> {
+        let shape = [1, 3, input_height, input_width];
+        let mut cached = self.input_buffer.lock().unwrap_or_else(|e| e.into_inner());
+        let reusable = cached.as_mut()
+            .is_some_and(|t| t.shape() == &shape[..] && std::sync::Arc::get_mut(t).is_some());
+        if !reusable {
+            *cached = Some(std::sync::Arc::new(Tensor::zero::<f32>(&shape)?));
+        }
+        let buffer = cached.as_mut()
+            .and_then(std::sync::Arc::get_mut)
+            .context("Input buffer is still in use")?;
+        write_chw_tensor(processed, buffer)?;
+        Ok(TValue::Const(cached.as_ref().context("Input buffer missing")?.clone()))
+    }
+    
+    /// 非极大值抑制；启用自适应时候选框越多阈值越低
+    fn apply_nms(&self, detections: Vec<DetectionRecord>) -> Vec<DetectionRecord> {
+        let nms_threshold = self.nms.threshold(detections.len());
+        let candidate_count = detections.len();
+        let detections = non_max_suppression(detections, nms_threshold);
+        eprintln!("NMS (IoU threshold {:.2}) kept {} of {} candidates", nms_threshold, detections.len(), candidate_count);
+        detections
+    }
+    
+    fn detect(&self, img_data: &[u8], width: u32, height: u32) -> Result<Vec<DetectionRecord>> {
+        if let Some(variant) = self.active_model() {
+            eprintln!("Running detection on image {}x{} with {}x{} model input",
+                     width, height, variant.input_width, variant.input_height);
+            
+            // 预处理
+            let (input_tensor, transform) = self.preprocess(img_data, width, height, variant.input_width, variant.input_height)?;
+            
+            self.run_inference(variant, input_tensor, transform, width, height)
+        } else {
+            eprintln!("No model loaded. Using mock detections.");
+            Ok(create_mock_detections(0))
+        }
+    }
+    
+    /// 直接对上游已预处理好的 NCHW f32 张量做检测，跳过自身的预处理；
+    /// shape 缺省时按模型输入形状解释数据，形状与模型输入不符时返回错误；
+    /// geometry 为上游的预处理几何方式，用于把检测框映射回 width x height 的原图
+    fn detect_tensor(&self, values: &[f32], shape: Option<&[usize]>, geometry: TensorGeometry, width: u32, height: u32) -> Result<Vec<DetectionRecord>> {
+        let variant = self.active_model().context("Tensor input requires a loaded model")?;
+        
+        let input_fact = variant.model.model().input_fact(0).context("Failed to read model input fact")?;
+        let expected: Vec<usize> = match input_fact.shape.as_concrete() {
+            Some(dims) => dims.to_vec(),
+            None => vec![1, 3, variant.input_height, variant.input_width],
+        };
+        let tensor = tensor_input(values, shape, &expected)?;
         
-        // 正确创建Tensor - 使用from_array_view方法
-        let tensor = tract_core::ndarray::ArrayD::<f32>::from_shape_vec(
-            final_array.shape().to_vec(),
-            final_array.into_raw_vec(),
-        ).context("Failed to create ndarray")?;
+        eprintln!("Running detection on pre-processed tensor {:?}", expected);
+        let transform = geometry.transform(width, height, variant.input_width, variant.input_height);
+        self.run_inference(variant, tensor.into(), transform, width, height)
+    }
+    
+    /// 推理（YOLO-World 模型额外输入文本嵌入）
+    fn infer(&self, variant: &ModelVariant, input_tensor: TValue) -> Result<TVec<TValue>> {
+        let inputs = match (&self.text_input, variant.accepts_text) {
+            (Some(text), true) => tvec!(input_tensor, text.clone().into()),
+            _ => tvec!(input_tensor),
+        };
+        variant.model.run(inputs).context("Model inference failed")
+    }
+    
+    /// 按配置或输出形状选择后处理实现
+    fn select_postprocessor(&self, shapes: &[&[usize]]) -> Option<&dyn PostProcessor> {
+        let selected = match &self.forced_postprocessor {
+            Some(name) => self.postprocessors.iter().find(|p| p.name() == name),
+            None => self.postprocessors.iter().find(|p| p.matches(shapes)),
+        };
+        selected.map(|p| p.as_ref())
+    }
+    
+    /// 后处理：解码为原图归一化坐标下的检测，需要时再做 NMS
+    fn decode_outputs(&self, outputs: &[TValue], variant: &ModelVariant, transform: ImageTransform, width: u32, height: u32) -> Vec<DetectionRecord> {
+        let shapes: Vec<&[usize]> = outputs.iter().map(|o| o.shape()).collect();
+        let Some(processor) = self.select_postprocessor(&shapes) else {
+            eprintln!("No postprocessor matches output shapes {:?}", shapes);
+            return Vec::new();
+        };
+        let ctx = PostprocessContext {
+            class_names: &self.class_names,
+            conf_threshold: self.conf_threshold,
+            class_thresholds: &self.class_thresholds,
+            transform,
+            input_width: variant.input_width as f32,
+            input_height: variant.input_height as f32,
+            img_width: width as f32,
+            img_height: height as f32,
+        };
+        let detections = processor.process(outputs, &ctx);
+        let detections = if processor.needs_nms() { self.apply_nms(detections) } else { detections };
+        eprintln!("Found {} objects with confidence > {} ({} postprocessor)", detections.len(), self.conf_threshold, processor.name());
+        detections
+    }
+    
+    /// 推理并后处理
+    fn run_inference(&self, variant: &ModelVariant, input_tensor: TValue, transform: ImageTransform, width: u32, height: u32) -> Result<Vec<DetectionRecord>> {
+        let outputs = self.infer(variant, input_tensor)?;
+        let detections = self.decode_outputs(&outputs, variant, transform, width, height);
         
-        Ok(Tensor::from(tensor))
+        eprintln!("Detection completed successfully. Found {} objects", detections.len());
+        Ok(detections)
+    }
+}
+
+/// 后处理所需的上下文
+struct PostprocessContext<'a> {
+    class_names: &'a [String],
+    conf_threshold: f32,
+    class_thresholds: &'a std::collections::HashMap<String, f32>,
+    transform: ImageTransform,  // 预处理的几何变换，用于把模型输入坐标映射回原图
+    input_width: f32,           // 模型输入尺寸
+    input_height: f32,
+    img_width: f32,
+    img_height: f32,
+}
+
+impl PostprocessContext<'_> {
+    /// 类别名，列表不足时为 `class_{idx}`
+    fn class_name(&self, class_idx: usize) -> String {
+        self.class_names.get(class_idx)
+            .cloned()
+            .unwrap_or_else(|| format!("class_{}", class_idx))
+    }
+    
+    /// 候选框置信度是否超过其类别的阈值（未单独配置的类别使用全局阈值）
+    fn accepts(&self, class_idx: usize, confidence: f32) -> bool {
+        let threshold = self.class_names.get(class_idx)
+            .and_then(|name| self.class_thresholds.get(name))
+            .copied()
+            .unwrap_or(self.conf_threshold);
+        confidence > threshold
+    }
+    
+    /// 所有类别中最低的阈值，用于按 objectness 提前过滤
+    fn min_threshold(&self) -> f32 {
+        self.class_thresholds.values().copied().fold(self.conf_threshold, f32::min)
+    }
+    
+    /// 将模型输入像素坐标下的中心点框映射为原图归一化坐标
+    fn to_normalized(&self, center_x: f32, center_y: f32, width: f32, height: f32) -> (f32, f32, f32, f32) {
+        let t = &self.transform;
+        (
+            (center_x - t.pad_x) / t.scale_x / self.img_width,
+            (center_y - t.pad_y) / t.scale_y / self.img_height,
+            width / t.scale_x / self.img_width,
+            height / t.scale_y / self.img_height,
+        )
+    }
+    
+    /// 生成一条检测；类别名列表不足时以 `class_{idx}` 命名，不丢弃检测
+    fn detection(&self, class_idx: usize, confidence: f32, index: usize, bbox: (f32, f32, f32, f32)) -> DetectionRecord {
+        let class_name = self.class_name(class_idx);
+        DetectionRecord {
+            name: format!("{}_{}", class_name, index),
+            class_name,
+            confidence,
+            x: bbox.0,
+            y: bbox.1,
+            width: bbox.2,
+            height: bbox.3,
+            extensions: Vec::new(),
+        }
+    }
+}
+
+/// 一种 YOLO 变体的后处理：把原始输出张量解码为原图归一化坐标下的检测。
+/// 新增变体只需实现该 trait 并加入 `default_postprocessors`
+trait PostProcessor {
+    fn name(&self) -> &'static str;
+    
+    /// 输出形状是否符合该变体，用于自动选择
+    fn matches(&self, shapes: &[&[usize]]) -> bool;
+    
+    /// 由输出形状推算的类别数，无法推算时返回 None
+    fn class_count(&self, shapes: &[&[usize]]) -> Option<usize>;
+    
+    /// 是否需要再做 NMS（端到端导出的模型已在图内去重）
+    fn needs_nms(&self) -> bool {
+        true
+    }
+    
+    fn process(&self, outputs: &[TValue], ctx: &PostprocessContext) -> Vec<DetectionRecord>;
+}
+
+/// 单个三维输出张量 [1, a, b] 的形状
+fn single_output_shape<'a>(shapes: &[&'a [usize]]) -> Option<&'a [usize]> {
+    match shapes {
+        [shape] if shape.len() == 3 && shape[0] == 1 => Some(shape),
+        _ => None,
+    }
+}
+
+/// YOLOv8 / YOLO-World：[1, 4 + 类别数, 锚点数]，无 objectness
+struct YoloV8PostProcessor;
+
+impl PostProcessor for YoloV8PostProcessor {
+    fn name(&self) -> &'static str {
+        "v8"
+    }
+    
+    fn matches(&self, shapes: &[&[usize]]) -> bool {
+        single_output_shape(shapes).is_some_and(|s| s[1] > 4 && s[1] < s[2])
+    }
+    
+    fn class_count(&self, shapes: &[&[usize]]) -> Option<usize> {
+        single_output_shape(shapes).filter(|s| s[1] > 4).map(|s| s[1] - 4)
     }
     
-    fn postprocess(&self, outputs: &Tensor, img_width: f32, img_height: f32) -> Vec<Detection> {
+    fn process(&self, outputs: &[TValue], ctx: &PostprocessContext) -> Vec<DetectionRecord> {
         let mut detections = Vec::new();
         
         // 获取输出数据
-        if let Ok(output_values) = outputs.to_array_view::<f32>() {
+        if let Ok(output_values) = outputs[0].to_array_view::<f32>() {
             let output_shape = output_values.shape();
             eprintln!("Output shape: {:?}", output_shape);
             
@@ -177,15 +596,14 @@ impl YoloDetector {
                 let detection_dim = 2;
                 
                 // 检查形状是否符合预期
-                if output_shape[batch_dim] == 1 && output_shape[channel_dim] >= 84 {
+                // 类别数由通道数推得（YOLO-World 的类别数等于提示词数量）
+                if output_shape[batch_dim] == 1 && output_shape[channel_dim] > 4 {
+                    let num_classes = output_shape[channel_dim] - 4;
                     let num_detections = output_shape[detection_dim];
                     eprintln!("Processing {} detections", num_detections);
                     
-                    // 限制处理的检测数量，避免过多
-                    let max_detections = num_detections.min(100);
-                    
-                    // 处理每个检测
-                    for i in 0..max_detections {
+                    // 处理全部锚点：低于阈值的候选先被过滤，剩余候选交给 NMS 去重
+                    for i in 0..num_detections {
                         let bbox_x = *output_values.get([0, 0, i]).unwrap_or(&0.0);
                         let bbox_y = *output_values.get([0, 1, i]).unwrap_or(&0.0);
                         let bbox_w = *output_values.get([0, 2, i]).unwrap_or(&0.0);
@@ -194,30 +612,18 @@ impl YoloDetector {
                         // 获取类别置信度
                         let mut max_conf = 0.0;
                         let mut max_class_idx = 0;
-                        for c in 0..80 {
-                            if 4 + c < output_shape[channel_dim] {
-                                let conf = *output_values.get([0, 4 + c, i]).unwrap_or(&0.0);
-                                if conf > max_conf {
-                                    max_conf = conf;
-                                    max_class_idx = c;
-                                }
+                        for c in 0..num_classes {
+                            let conf = *output_values.get([0, 4 + c, i]).unwrap_or(&0.0);
+                            if conf > max_conf {
+                                max_conf = conf;
+                                max_class_idx = c;
                             }
                         }
                         
-                        // 应用置信度阈值
-                        if max_conf > 0.1 && (max_class_idx as usize) < self.class_names.len() {
-                            // 生成唯一标识名
-                            let object_id = format!("{}_{}", self.class_names[max_class_idx as usize], i);
-                            
-                            detections.push(Detection {
-                                name: object_id,
-                                class_name: self.class_names[max_class_idx as usize].clone(),
-                                confidence: max_conf,
-                                x: bbox_x / img_width,
-                                y: bbox_y / img_height,
-                                width: bbox_w / img_width,
-                                height: bbox_h / img_height,
-                            });
+                        // 应用置信度阈值；坐标在模型输入空间，去掉填充和缩放后再按原图归一化
+                        if ctx.accepts(max_class_idx, max_conf) {
+                            let bbox = ctx.to_normalized(bbox_x, bbox_y, bbox_w, bbox_h);
+                            detections.push(ctx.detection(max_class_idx, max_conf, i, bbox));
                         }
                     }
                 } else {
@@ -229,97 +635,1261 @@ impl YoloDetector {
         } else {
             eprintln!("Failed to convert output tensor to array view");
         }
-        
-        eprintln!("Found {} objects with confidence > 0.5", detections.len());
         detections
     }
+}
+
+/// YOLOv5：[1, 锚框数, 5 + 类别数]，每行为 cx, cy, w, h, objectness, 各类别分数
+struct YoloV5PostProcessor;
+
+impl PostProcessor for YoloV5PostProcessor {
+    fn name(&self) -> &'static str {
+        "v5"
+    }
     
-    fn detect(&self, img_data: &[u8], width: u32, height: u32) -> Result<Vec<Detection>> {
-        if let Some(ref model) = self.model {
-            eprintln!("Running detection on image {}x{}", width, height);
-            
-            // 预处理
-            let input_tensor = self.preprocess(img_data, width, height)?;
-            
-            // 推理
-            let outputs = model.run(tvec!(input_tensor.into()))
-                .context("Model inference failed")?;
-            
-            // 获取输出
-            let output_tensor = &outputs[0];
-            
-            // 后处理
-            let detections = self.postprocess(output_tensor, width as f32, height as f32);
-            
-            eprintln!("Detection completed successfully. Found {} objects", detections.len());
-            Ok(detections)
-        } else {
-            eprintln!("No model loaded. Using mock detections.");
-            Ok(create_mock_detections(0))
+    fn matches(&self, shapes: &[&[usize]]) -> bool {
+        single_output_shape(shapes).is_some_and(|s| s[2] > 5 && s[1] > s[2])
+    }
+    
+    fn class_count(&self, shapes: &[&[usize]]) -> Option<usize> {
+        single_output_shape(shapes).filter(|s| s[2] > 5).map(|s| s[2] - 5)
+    }
+    
+    fn process(&self, outputs: &[TValue], ctx: &PostprocessContext) -> Vec<DetectionRecord> {
+        let Ok(view) = outputs[0].to_array_view::<f32>() else {
+            eprintln!("Failed to convert output tensor to array view");
+            return Vec::new();
+        };
+        let Some(shape) = single_output_shape(&[view.shape()]).filter(|s| s[2] > 5) else {
+            eprintln!("Unexpected YOLOv5 output shape: {:?}", view.shape());
+            return Vec::new();
+        };
+        let (num_boxes, channels) = (shape[1], shape[2]);
+        
+        let mut detections = Vec::new();
+        for i in 0..num_boxes {
+            let objectness = view[[0, i, 4]];
+            if objectness <= ctx.min_threshold() {
+                continue;
+            }
+            let (class_idx, class_score) = (5..channels)
+                .map(|c| (c - 5, view[[0, i, c]]))
+                .fold((0, 0.0), |best, cur| if cur.1 > best.1 { cur } else { best });
+            let confidence = objectness * class_score;
+            if ctx.accepts(class_idx, confidence) {
+                let bbox = ctx.to_normalized(view[[0, i, 0]], view[[0, i, 1]], view[[0, i, 2]], view[[0, i, 3]]);
+                detections.push(ctx.detection(class_idx, confidence, i, bbox));
+            }
         }
+        detections
     }
 }
 
-fn main() -> Result<()> {
-    // 在最开始就初始化日志系统
-    env_logger::init();
+/// YOLOv10 端到端导出：[1, 最大检测数, 6]，每行为 x1, y1, x2, y2, score, class_id，已在图内去重
+struct YoloV10PostProcessor;
+
+impl PostProcessor for YoloV10PostProcessor {
+    fn name(&self) -> &'static str {
+        "v10"
+    }
     
-    // 立即打印启动信息
-    println!("Detector node: Starting... (stdout)");
-    eprintln!("Detector node: Starting... (stderr)");
-    eprintln!("Detector node: Starting... (info)");
+    fn matches(&self, shapes: &[&[usize]]) -> bool {
+        single_output_shape(shapes).is_some_and(|s| s[2] == 6 && s[1] <= 1000)
+    }
     
-    let (mut node, mut event_stream) = match DoraNode::init_from_env() {
-        Ok(n) => {
-            eprintln!("Detector node: Dora node initialized successfully");
-            n
-        },
-        Err(e) => {
-            eprintln!("Detector node: Failed to initialize DoraNode: {}", e);
-            std::process::exit(1);
+    fn class_count(&self, _shapes: &[&[usize]]) -> Option<usize> {
+        None
+    }
+    
+    fn needs_nms(&self) -> bool {
+        false
+    }
+    
+    fn process(&self, outputs: &[TValue], ctx: &PostprocessContext) -> Vec<DetectionRecord> {
+        let Ok(view) = outputs[0].to_array_view::<f32>() else {
+            eprintln!("Failed to convert output tensor to array view");
+            return Vec::new();
+        };
+        if single_output_shape(&[view.shape()]).map_or(true, |s| s[2] != 6) {
+            eprintln!("Unexpected YOLOv10 output shape: {:?}", view.shape());
+            return Vec::new();
         }
-    };
-
-    // 初始化YOLO检测器
-    let model_path = "models/yolov8n.onnx";
-    let detector = match YoloDetector::new(model_path) {
-        Ok(d) => {
-            eprintln!("Detector node: YOLO detector initialized");
-            d
-        },
-        Err(e) => {
-            eprintln!("Detector node: Failed to initialize YOLO detector: {}", e);
-            return Err(e);
+        
+        let mut detections = Vec::new();
+        for i in 0..view.shape()[1] {
+            let score = view[[0, i, 4]];
+            let class_idx = view[[0, i, 5]].max(0.0) as usize;
+            if !ctx.accepts(class_idx, score) {
+                continue;
+            }
+            let (x1, y1, x2, y2) = (view[[0, i, 0]], view[[0, i, 1]], view[[0, i, 2]], view[[0, i, 3]]);
+            let bbox = ctx.to_normalized((x1 + x2) / 2.0, (y1 + y2) / 2.0, x2 - x1, y2 - y1);
+            detections.push(ctx.detection(class_idx, score, i, bbox));
         }
-    };
-
-    let mut frame_counter = 0;
-    eprintln!("Detector node: Ready to receive data");
+        detections
+    }
+}
 
-    // 自适应跳帧机制
-    let mut skip_counter = 0;
-    let mut process_interval = 1; // 初始为每帧都处理
+/// YOLOv7 多检测头输出：每个头为 [1, 锚框数, ny, nx, 5 + 类别数] 的原始输出，
+/// 按各自的步长和锚框解码后合并
+struct YoloV7PostProcessor {
+    anchors: Vec<Vec<(f32, f32)>>,  // 各检测头的锚框，按步长从小到大
+}
 
-    loop {
-        // 添加调试日志，查看是否能接收到任何事件
-        eprintln!("Detector node: Waiting for event...");
+impl PostProcessor for YoloV7PostProcessor {
+    fn name(&self) -> &'static str {
+        "v7"
+    }
+    
+    fn matches(&self, shapes: &[&[usize]]) -> bool {
+        shapes.len() > 1 && shapes.iter().all(|s| s.len() == 5 && s[0] == 1 && s[4] > 5)
+    }
+    
+    fn class_count(&self, shapes: &[&[usize]]) -> Option<usize> {
+        shapes.first().filter(|s| s.len() == 5 && s[4] > 5).map(|s| s[4] - 5)
+    }
+    
+    fn process(&self, outputs: &[TValue], ctx: &PostprocessContext) -> Vec<DetectionRecord> {
+        let mut heads = Vec::new();
+        for (idx, output) in outputs.iter().enumerate() {
+            match output.to_array_view::<f32>() {
+                Ok(view) if view.ndim() == 5 && view.shape()[0] == 1 && view.shape()[4] > 5 => heads.push(view),
+                Ok(view) => eprintln!("Detector node: Skipping output {} with unexpected head shape {:?}", idx, view.shape()),
+                Err(e) => eprintln!("Detector node: Failed to read output {}: {}", idx, e),
+            }
+        }
+        // 网格越密步长越小，按步长从小到大对应 P3/P4/P5 锚框
+        heads.sort_by_key(|head| std::cmp::Reverse(head.shape()[2]));
         
-        if let Some(event) = event_stream.recv_timeout(Duration::from_millis(1000)) {
-            eprintln!("Detector node: Received an event");
+        let mut detections = Vec::new();
+        for (head_idx, head) in heads.iter().enumerate() {
+            let Some(anchors) = self.anchors.get(head_idx) else {
+                eprintln!("Detector node: No anchors configured for detection head {}, skipping", head_idx);
+                continue;
+            };
+            let shape = head.shape();
+            let (num_anchors, grid_h, grid_w, channels) = (shape[1], shape[2], shape[3], shape[4]);
+            let stride_x = ctx.input_width / grid_w as f32;
+            let stride_y = ctx.input_height / grid_h as f32;
+            let num_classes = channels - 5;
             
-            match event {
-                Event::Input { id, data, metadata } => {
-                    eprintln!("Detector node: Received input with id '{}'", id);
-                    
-                    // 打印所有元数据参数，帮助调试
-                    eprintln!("Detector node: Metadata parameters: {:?}", metadata.parameters);
-                    
-                    if id.as_str() == "frame" {
-                        eprintln!("Detector node: Processing frame input with id 'frame'");
+            for a in 0..num_anchors.min(anchors.len()) {
+                let (anchor_w, anchor_h) = anchors[a];
+                for gy in 0..grid_h {
+                    for gx in 0..grid_w {
+                        let value = |c: usize| sigmoid(head[[0, a, gy, gx, c]]);
+                        let objectness = value(4);
+                        if objectness <= ctx.min_threshold() {
+                            continue;
+                        }
+                        let mut max_conf = 0.0;
+                        let mut max_class_idx = 0;
+                        for c in 0..num_classes {
+                            let conf = objectness * value(5 + c);
+                            if conf > max_conf {
+                                max_conf = conf;
+                                max_class_idx = c;
+                            }
+                        }
+                        if !ctx.accepts(max_class_idx, max_conf) {
+                            continue;
+                        }
                         
-                        // 从元数据中获取图像尺寸 - 使用更灵活的方式
-                        let width = match metadata.parameters.get("width") {
-                            Some(dora_node_api::Parameter::String(s)) => s.parse::<u32>().ok().unwrap_or(640),
+                        let center_x = (value(0) * 2.0 - 0.5 + gx as f32) * stride_x;
+                        let center_y = (value(1) * 2.0 - 0.5 + gy as f32) * stride_y;
+                        let box_w = (value(2) * 2.0).powi(2) * anchor_w;
+                        let box_h = (value(3) * 2.0).powi(2) * anchor_h;
+                        
+                        let bbox = ctx.to_normalized(center_x, center_y, box_w, box_h);
+                        detections.push(ctx.detection(max_class_idx, max_conf, detections.len(), bbox));
+                    }
+                }
+            }
+        }
+        eprintln!("Decoded {} candidates from {} detection heads", detections.len(), heads.len());
+        detections
+    }
+}
+
+/// 内置的后处理实现，自动选择时按此顺序匹配输出形状
+fn default_postprocessors() -> Vec<Box<dyn PostProcessor>> {
+    vec![
+        Box::new(YoloV7PostProcessor { anchors: anchors_from_env() }),
+        Box::new(YoloV10PostProcessor),
+        Box::new(YoloV8PostProcessor),
+        Box::new(YoloV5PostProcessor),
+    ]
+}
+
+/// YOLOv7 默认锚框（P3/P4/P5，输入像素单位）
+const YOLOV7_ANCHORS: [[(f32, f32); 3]; 3] = [
+    [(12.0, 16.0), (19.0, 36.0), (40.0, 28.0)],
+    [(36.0, 75.0), (76.0, 55.0), (72.0, 146.0)],
+    [(142.0, 110.0), (192.0, 243.0), (459.0, 401.0)],
+];
+
+/// 从 YOLO_ANCHORS 读取锚框，格式为 "w,h,w,h,...;w,h,..."，每个检测头用分号分隔；
+/// 未设置或格式错误时使用 YOLOv7 默认锚框
+fn anchors_from_env() -> Vec<Vec<(f32, f32)>> {
+    let default = || YOLOV7_ANCHORS.iter().map(|head| head.to_vec()).collect();
+    match std::env::var("YOLO_ANCHORS") {
+        Ok(spec) => parse_anchors(&spec).unwrap_or_else(|e| {
+            eprintln!("Detector node: Invalid YOLO_ANCHORS '{}': {}, using YOLOv7 defaults", spec, e);
+            default()
+        }),
+        Err(_) => default(),
+    }
+}
+
+fn parse_anchors(spec: &str) -> Result<Vec<Vec<(f32, f32)>>> {
+    spec.split(';')
+        .map(|head| {
+            let values = head.split(',')
+                .map(|v| v.trim().parse::<f32>().with_context(|| format!("invalid anchor value '{}'", v.trim())))
+                .collect::<Result<Vec<f32>>>()?;
+            if values.is_empty() || values.len() % 2 != 0 {
+                anyhow::bail!("each head needs width,height pairs, got {} values", values.len());
+            }
+            Ok(values.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+        })
+        .collect()
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// 单阶段耗时的统计
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TimingSummary {
+    mean_ms: f64,
+    p50_ms: f64,
+    p95_ms: f64,
+}
+
+impl TimingSummary {
+    /// 由一组耗时样本计算均值与分位数（最近秩法），样本为空时返回 None
+    fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let percentile = |p: f64| {
+            let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Some(Self {
+            mean_ms: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p50_ms: percentile(50.0),
+            p95_ms: percentile(95.0),
+        })
+    }
+}
+
+/// 启动自测：对合成帧连续推理，分别统计预处理、推理、后处理耗时并打印一次报告
+fn run_self_profile(detector: &YoloDetector, iterations: u32, width: u32, height: u32) -> Result<()> {
+    let Some(variant) = detector.active_model() else {
+        eprintln!("Detector node: Self-profile skipped, no model loaded");
+        return Ok(());
+    };
+    let img_data = vec![114u8; (width * height * 3) as usize];
+    
+    // 预热一次，避免首帧冷启动影响统计
+    detector.detect(&img_data, width, height)?;
+    
+    let (mut preprocess_ms, mut inference_ms, mut postprocess_ms) = (Vec::new(), Vec::new(), Vec::new());
+    for _ in 0..iterations {
+        let start = std::time::Instant::now();
+        let (input_tensor, transform) = detector.preprocess(&img_data, width, height, variant.input_width, variant.input_height)?;
+        preprocess_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+        
+        let start = std::time::Instant::now();
+        let outputs = detector.infer(variant, input_tensor)?;
+        inference_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+        
+        let start = std::time::Instant::now();
+        detector.decode_outputs(&outputs, variant, transform, width, height);
+        postprocess_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+    }
+    
+    let total_ms: Vec<f64> = (0..preprocess_ms.len())
+        .map(|i| preprocess_ms[i] + inference_ms[i] + postprocess_ms[i])
+        .collect();
+    eprintln!("Detector node: Self-profile: {} iterations on {}x{} synthetic frame, {}x{} model input",
+             iterations, width, height, variant.input_width, variant.input_height);
+    for (stage, samples) in [("preprocess", &preprocess_ms), ("inference", &inference_ms),
+                             ("postprocess", &postprocess_ms), ("total", &total_ms)] {
+        if let Some(summary) = TimingSummary::from_samples(samples) {
+            eprintln!("Detector node:   {:<11} mean {:.2} ms, p50 {:.2} ms, p95 {:.2} ms",
+                     stage, summary.mean_ms, summary.p50_ms, summary.p95_ms);
+        }
+    }
+    if let Some(total) = TimingSummary::from_samples(&total_ms) {
+        eprintln!("Detector node:   implied max FPS ~{:.1} (from mean total)", 1000.0 / total.mean_ms);
+    }
+    Ok(())
+}
+
+/// 读取类别名文件：每行一个类别名，忽略空行
+fn load_class_names(path: &str) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let names: Vec<String> = content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    if names.is_empty() {
+        anyhow::bail!("{} contains no class names", path);
+    }
+    Ok(names)
+}
+
+/// 从 YOLO_CLASS_THRESHOLDS 读取按类别的阈值，格式为 `person=0.25,traffic light=0.6`；
+/// 非法或超出 [0, 1] 的条目打印警告后忽略
+fn class_thresholds_from_env() -> std::collections::HashMap<String, f32> {
+    let mut thresholds = std::collections::HashMap::new();
+    let Ok(spec) = std::env::var("YOLO_CLASS_THRESHOLDS") else {
+        return thresholds;
+    };
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=').map(|(name, value)| (name.trim(), value.trim().parse::<f32>())) {
+            Some((name, Ok(value))) if !name.is_empty() && (0.0..=1.0).contains(&value) => {
+                thresholds.insert(name.to_string(), value);
+            }
+            _ => eprintln!("Invalid YOLO_CLASS_THRESHOLDS entry '{}', ignoring", entry),
+        }
+    }
+    if !thresholds.is_empty() {
+        eprintln!("Per-class confidence thresholds: {:?}", thresholds);
+    }
+    thresholds
+}
+
+/// 从优化后的模型读回 [1, 3, H, W] 图像输入的宽高，形状不是具体值时返回 None
+fn concrete_input_size(model: &YoloModel) -> Option<(usize, usize)> {
+    let fact = model.model().input_fact(0).ok()?;
+    match fact.shape.as_concrete()? {
+        [1, 3, height, width] => Some((*width, *height)),
+        _ => None,
+    }
+}
+
+/// 比对模型推算出的类别数与类别名列表长度，不一致时返回诊断信息；
+/// fix 为 true 时将列表截断到模型类别数，或用 `class_{idx}` 补齐
+fn reconcile_class_names(class_names: &mut Vec<String>, implied_classes: usize, fix: bool) -> Option<String> {
+    let configured = class_names.len();
+    if configured == implied_classes {
+        return None;
+    }
+    let mut message = format!(
+        "model outputs {} classes but {} class names are configured; \
+         labels will be wrong. Provide a matching class names list",
+        implied_classes, configured
+    );
+    if fix {
+        if configured > implied_classes {
+            class_names.truncate(implied_classes);
+        } else {
+            class_names.extend((configured..implied_classes).map(|idx| format!("class_{}", idx)));
+        }
+        message.push_str(&format!("; class names adjusted to {} entries", implied_classes));
+    } else {
+        message.push_str(" or set YOLO_FIX_CLASS_NAMES=1 to truncate/extend automatically");
+    }
+    Some(message)
+}
+
+/// 检测节点的一帧输入：原始BGR图像，或上游已预处理好的张量
+enum FrameInput {
+    Image(Vec<u8>),
+    Tensor { values: Vec<f32>, shape: Option<Vec<usize>>, geometry: TensorGeometry },
+}
+
+/// 读取 tensor 输入的数据，只接受 Float32Array
+fn tensor_values(data: &dyn ArrowArray) -> Result<Vec<f32>> {
+    let array = data.as_any().downcast_ref::<Float32Array>()
+        .with_context(|| format!("Expected Float32Array on 'tensor' input, got {:?}", data.data_type()))?;
+    Ok(array.values().to_vec())
+}
+
+/// 校验上游张量的形状与长度后构造模型输入；shape 缺省时按模型输入形状 expected 解释数据，
+/// 形状或长度不符时返回错误，不把错位的数据交给模型
+fn tensor_input(values: &[f32], shape: Option<&[usize]>, expected: &[usize]) -> Result<Tensor> {
+    let shape = shape.unwrap_or(expected);
+    if shape != expected {
+        anyhow::bail!("Input tensor shape {:?} does not match model input shape {:?}", shape, expected);
+    }
+    if values.len() != expected.iter().product::<usize>() {
+        anyhow::bail!("Input tensor has {} values, expected {} for shape {:?}", values.len(), expected.iter().product::<usize>(), expected);
+    }
+    let array = tract_core::ndarray::ArrayD::<f32>::from_shape_vec(expected.to_vec(), values.to_vec())
+        .context("Failed to create input tensor")?;
+    Ok(Tensor::from(array))
+}
+
+/// 上游预处理张量时的几何方式，用于把检测框映射回原图
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TensorGeometry {
+    Letterbox,                 // 保持宽高比缩放并居中填充（与本节点默认预处理相同）
+    Stretch,                   // 直接缩放到模型输入尺寸
+    Explicit(ImageTransform),  // 元数据给出的缩放比例与填充量
+}
+
+impl TensorGeometry {
+    /// 从 tensor 输入的元数据读取：给出 `scale`（可带 `pad_x`/`pad_y`，缺省为 0）时直接使用，
+    /// 否则按 `preprocess`（`letterbox`，默认；或 `stretch`）推算
+    fn from_parameters(parameters: &MetadataParameters) -> Result<Self> {
+        let text = |key: &str| match parameters.get(key) {
+            Some(dora_node_api::Parameter::String(s)) => Some(s.trim().to_string()),
+            Some(dora_node_api::Parameter::Integer(i)) => Some(i.to_string()),
+            _ => None,
+        };
+        let number = |key: &str| -> Result<Option<f32>> {
+            text(key).map(|v| v.parse::<f32>().with_context(|| format!("invalid {} '{}'", key, v))).transpose()
+        };
+        if let Some(scale) = number("scale")? {
+            if scale.is_nan() || scale <= 0.0 {
+                anyhow::bail!("scale must be positive, got {}", scale);
+            }
+            let (pad_x, pad_y) = (number("pad_x")?.unwrap_or(0.0), number("pad_y")?.unwrap_or(0.0));
+            return Ok(TensorGeometry::Explicit(ImageTransform { scale_x: scale, scale_y: scale, pad_x, pad_y }));
+        }
+        match text("preprocess").as_deref() {
+            None | Some("letterbox") => Ok(TensorGeometry::Letterbox),
+            Some("stretch") => Ok(TensorGeometry::Stretch),
+            Some(other) => anyhow::bail!("unknown preprocess '{}', expected letterbox or stretch", other),
+        }
+    }
+    
+    /// 原图 (width, height) 预处理到模型输入 (input_width, input_height) 的几何变换
+    fn transform(&self, width: u32, height: u32, input_width: usize, input_height: usize) -> ImageTransform {
+        match *self {
+            TensorGeometry::Letterbox => {
+                let (scale, _, _, pad_x, pad_y) = letterbox_geometry(width as i32, height as i32, input_width as i32, input_height as i32);
+                ImageTransform { scale_x: scale, scale_y: scale, pad_x: pad_x as f32, pad_y: pad_y as f32 }
+            }
+            TensorGeometry::Stretch => ImageTransform {
+                scale_x: input_width as f32 / width as f32,
+                scale_y: input_height as f32 / height as f32,
+                pad_x: 0.0,
+                pad_y: 0.0,
+            },
+            TensorGeometry::Explicit(transform) => transform,
+        }
+    }
+}
+
+/// 预处理步骤
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PreprocessStep {
+    Resize,             // 直接缩放到模型输入尺寸（可能拉伸）
+    Letterbox,          // 保持宽高比缩放，剩余部分用灰色(114)填充
+    ColorConvert(i32),  // OpenCV 颜色转换码，如 COLOR_BGR2RGB
+    Normalize(f64),     // 转为 f32 并乘以系数，如 1/255
+    ToChw,              // HWC → CHW 并添加 batch 维度，必须是最后一步
+}
+
+/// 预处理的几何变换：模型输入坐标 = 原图坐标 * scale + pad
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ImageTransform {
+    scale_x: f32,
+    scale_y: f32,
+    pad_x: f32,
+    pad_y: f32,
+}
+
+/// 可配置的预处理步骤序列
+#[derive(Debug, Clone)]
+struct PreprocessPipeline {
+    steps: Vec<PreprocessStep>,
+    interpolation: i32,  // resize/letterbox 使用的 OpenCV 插值方式
+}
+
+impl PreprocessPipeline {
+    /// 内置预设：
+    /// - `letterbox`：Ultralytics 标准的保持宽高比缩放 + 灰色(114)填充（默认，与 YOLOv8 训练时一致）
+    /// - `stretch`：BGR→RGB、直接缩放、/255、CHW，非方形输入会被拉伸
+    fn preset(name: &str) -> Option<Self> {
+        let steps = match name {
+            "stretch" => vec![
+                PreprocessStep::ColorConvert(imgproc::COLOR_BGR2RGB),
+                PreprocessStep::Resize,
+                PreprocessStep::Normalize(1.0 / 255.0),
+                PreprocessStep::ToChw,
+            ],
+            "letterbox" => vec![
+                PreprocessStep::Letterbox,
+                PreprocessStep::ColorConvert(imgproc::COLOR_BGR2RGB),
+                PreprocessStep::Normalize(1.0 / 255.0),
+                PreprocessStep::ToChw,
+            ],
+            _ => return None,
+        };
+        Some(Self { steps, interpolation: imgproc::INTER_LINEAR })
+    }
+    
+    /// 解析逗号分隔的步骤列表，如 `letterbox,bgr2rgb,normalize,chw`
+    fn parse(spec: &str) -> Result<Self> {
+        if let Some(preset) = Self::preset(spec.trim()) {
+            return Ok(preset);
+        }
+        let steps = spec.split(',').map(|token| {
+            Ok(match token.trim() {
+                "resize" => PreprocessStep::Resize,
+                "letterbox" => PreprocessStep::Letterbox,
+                "bgr2rgb" | "rgb2bgr" => PreprocessStep::ColorConvert(imgproc::COLOR_BGR2RGB),
+                "normalize" => PreprocessStep::Normalize(1.0 / 255.0),
+                "chw" => PreprocessStep::ToChw,
+                other => anyhow::bail!("Unknown preprocessing step '{}'", other),
+            })
+        }).collect::<Result<Vec<_>>>()?;
+        
+        let geometric = steps.iter().filter(|s| matches!(s, PreprocessStep::Resize | PreprocessStep::Letterbox)).count();
+        if geometric != 1 {
+            anyhow::bail!("Preprocessing must contain exactly one of resize/letterbox, got {}", geometric);
+        }
+        if steps.iter().position(|s| *s == PreprocessStep::ToChw) != Some(steps.len() - 1) {
+            anyhow::bail!("Preprocessing must end with a single chw step");
+        }
+        Ok(Self { steps, interpolation: imgproc::INTER_LINEAR })
+    }
+    
+    fn from_env() -> Self {
+        let spec: String = env_or("YOLO_PREPROCESS", "letterbox".to_string());
+        let mut pipeline = match Self::parse(&spec) {
+            Ok(pipeline) => {
+                eprintln!("Preprocessing steps: {:?}", pipeline.steps);
+                pipeline
+            }
+            Err(e) => {
+                eprintln!("Invalid YOLO_PREPROCESS '{}': {:#}, using default", spec, e);
+                Self::preset("letterbox").unwrap()
+            }
+        };
+        let interp: String = env_or("YOLO_RESIZE_INTERP", "linear".to_string());
+        match interpolation_flag(&interp) {
+            Some(flag) => pipeline.interpolation = flag,
+            None => eprintln!("Invalid YOLO_RESIZE_INTERP '{}', expected linear/area/cubic/nearest, using linear", interp),
+        }
+        pipeline
+    }
+    
+    /// 依次执行 ToChw 之前的图像步骤；M 可以是 Mat 或 UMat
+    fn run_image_steps<M>(&self, src: M, new: impl Fn() -> M, target_size: opencv::core::Size) -> Result<(M, ImageTransform)>
+    where
+        M: opencv::core::ToInputArray + opencv::core::ToOutputArray,
+    {
+        let mut current = src;
+        let mut transform = ImageTransform { scale_x: 1.0, scale_y: 1.0, pad_x: 0.0, pad_y: 0.0 };
+        
+        for step in &self.steps {
+            let mut dst = new();
+            match *step {
+                PreprocessStep::Resize => {
+                    let size = current.input_array()?.size(-1)?;
+                    imgproc::resize(&current, &mut dst, target_size, 0.0, 0.0, self.interpolation)
+                        .context("Failed to resize image")?;
+                    transform.scale_x = target_size.width as f32 / size.width as f32;
+                    transform.scale_y = target_size.height as f32 / size.height as f32;
+                }
+                PreprocessStep::Letterbox => {
+                    let size = current.input_array()?.size(-1)?;
+                    let (scale, new_w, new_h, pad_x, pad_y) = letterbox_geometry(size.width, size.height, target_size.width, target_size.height);
+                    let mut resized = new();
+                    imgproc::resize(&current, &mut resized, opencv::core::Size::new(new_w, new_h), 0.0, 0.0, self.interpolation)
+                        .context("Failed to resize image")?;
+                    opencv::core::copy_make_border(
+                        &resized,
+                        &mut dst,
+                        pad_y,
+                        target_size.height - new_h - pad_y,
+                        pad_x,
+                        target_size.width - new_w - pad_x,
+                        opencv::core::BORDER_CONSTANT,
+                        opencv::core::Scalar::all(114.0),
+                    ).context("Failed to pad image")?;
+                    transform = ImageTransform { scale_x: scale, scale_y: scale, pad_x: pad_x as f32, pad_y: pad_y as f32 };
+                }
+                PreprocessStep::ColorConvert(code) => {
+                    imgproc::cvt_color(&current, &mut dst, code, 0)
+                        .context("Failed to convert color space")?;
+                }
+                PreprocessStep::Normalize(scale) => {
+                    opencv::core::multiply(&current, &opencv::core::Scalar::all(scale), &mut dst, 1.0, opencv::core::CV_32F)
+                        .context("Failed to normalize image")?;
+                }
+                PreprocessStep::ToChw => break,
+            }
+            current = dst;
+        }
+        Ok((current, transform))
+    }
+}
+
+/// 插值方式名称对应的 OpenCV 标志；大幅缩小时 `area` 画质更好，`nearest` 最快
+fn interpolation_flag(name: &str) -> Option<i32> {
+    match name {
+        "linear" => Some(imgproc::INTER_LINEAR),
+        "area" => Some(imgproc::INTER_AREA),
+        "cubic" => Some(imgproc::INTER_CUBIC),
+        "nearest" => Some(imgproc::INTER_NEAREST),
+        _ => None,
+    }
+}
+
+/// 计算 letterbox 的缩放比例、缩放后尺寸以及左/上填充量
+fn letterbox_geometry(src_w: i32, src_h: i32, dst_w: i32, dst_h: i32) -> (f32, i32, i32, i32, i32) {
+    let scale = (dst_w as f32 / src_w as f32).min(dst_h as f32 / src_h as f32);
+    let new_w = ((src_w as f32 * scale).round() as i32).min(dst_w);
+    let new_h = ((src_h as f32 * scale).round() as i32).min(dst_h);
+    let pad_x = (dst_w - new_w) / 2;
+    let pad_y = (dst_h - new_h) / 2;
+    (scale, new_w, new_h, pad_x, pad_y)
+}
+
+/// 将预处理后的 HWC 图像写入 [1, 3, H, W] 的 f32 张量（未归一化的图像按原值转换）。
+/// 由 blob_from_image 一次完成 HWC→CHW 与类型转换，颜色顺序和归一化已由预处理步骤决定，这里不再改动
+fn write_chw_tensor(processed: &Mat, tensor: &mut Tensor) -> Result<()> {
+    let blob = opencv::dnn::blob_from_image(
+        processed,
+        1.0,
+        opencv::core::Size::default(),
+        opencv::core::Scalar::default(),
+        false,
+        false,
+        opencv::core::CV_32F,
+    ).context("Failed to convert image to CHW blob")?;
+    let values = blob.data_typed::<f32>().context("Failed to read CHW blob")?;
+    if values.len() != tensor.len() {
+        anyhow::bail!("Preprocessed image has {} values, expected {} for input shape {:?}",
+                      values.len(), tensor.len(), tensor.shape());
+    }
+    tensor.as_slice_mut::<f32>().context("Input buffer is not f32")?.copy_from_slice(values);
+    Ok(())
+}
+
+/// NMS 的 IoU 阈值配置；启用自适应时按候选框数量在 [min, max] 之间线性调整
+struct NmsConfig {
+    threshold: f32,
+    adaptive: bool,
+    min_threshold: f32,
+    max_threshold: f32,
+    sparse_count: usize,  // 候选数不超过该值时使用 max_threshold
+    dense_count: usize,   // 候选数不少于该值时使用 min_threshold
+}
+
+impl NmsConfig {
+    fn from_env() -> Self {
+        let min_threshold = env_unit_interval("YOLO_NMS_MIN", 0.3);
+        let max_threshold = env_unit_interval("YOLO_NMS_MAX", 0.6);
+        let sparse_count: usize = env_or("YOLO_NMS_SPARSE_COUNT", 10);
+        let dense_count: usize = env_or("YOLO_NMS_DENSE_COUNT", 100);
+        Self {
+            threshold: env_unit_interval("YOLO_NMS_THRESHOLD", 0.45),
+            adaptive: env_or("YOLO_ADAPTIVE_NMS", false),
+            min_threshold: min_threshold.min(max_threshold),
+            max_threshold: max_threshold.max(min_threshold),
+            sparse_count: sparse_count.min(dense_count),
+            dense_count: dense_count.max(sparse_count),
+        }
+    }
+    
+    /// 当前候选框数量下的有效 IoU 阈值
+    fn threshold(&self, candidate_count: usize) -> f32 {
+        if !self.adaptive {
+            return self.threshold;
+        }
+        if candidate_count <= self.sparse_count {
+            self.max_threshold
+        } else if candidate_count >= self.dense_count {
+            self.min_threshold
+        } else {
+            let t = (candidate_count - self.sparse_count) as f32 / (self.dense_count - self.sparse_count) as f32;
+            self.max_threshold - t * (self.max_threshold - self.min_threshold)
+        }
+    }
+}
+
+/// 将中心点形式 (x, y, w, h) 转为角点形式 (x1, y1, x2, y2)
+fn center_to_corners(d: &DetectionRecord) -> (f32, f32, f32, f32) {
+    (d.x - d.width / 2.0, d.y - d.height / 2.0, d.x + d.width / 2.0, d.y + d.height / 2.0)
+}
+
+/// 两个检测框的IoU（在同一坐标系下计算，先转换为角点形式）
+fn detection_iou(a: &DetectionRecord, b: &DetectionRecord) -> f32 {
+    let (ax1, ay1, ax2, ay2) = center_to_corners(a);
+    let (bx1, by1, bx2, by2) = center_to_corners(b);
+    let inter_w = (ax2.min(bx2) - ax1.max(bx1)).max(0.0);
+    let inter_h = (ay2.min(by2) - ay1.max(by1)).max(0.0);
+    let inter = inter_w * inter_h;
+    let union = a.width * a.height + b.width * b.height - inter;
+    if union <= 0.0 { 0.0 } else { inter / union }
+}
+
+/// 按类别的非极大值抑制：按置信度降序，抑制与已保留同类框 IoU 超过阈值的框
+fn non_max_suppression(mut detections: Vec<DetectionRecord>, iou_threshold: f32) -> Vec<DetectionRecord> {
+    detections.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal));
+    let mut kept: Vec<DetectionRecord> = Vec::with_capacity(detections.len());
+    for candidate in detections {
+        let suppressed = kept.iter().any(|k| {
+            k.class_name == candidate.class_name && detection_iou(k, &candidate) > iou_threshold
+        });
+        if !suppressed {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+/// 输出载荷：原始字节（图像帧等）或 Arrow 数组（检测结果）
+enum OutputData {
+    Bytes(Vec<u8>),
+    Array(ArrayRef),
+}
+
+/// 待发送的一条输出
+struct OutputMessage {
+    id: String,
+    parameters: MetadataParameters,
+    data: OutputData,
+}
+
+fn send_output_data(node: &mut DoraNode, id: &str, parameters: MetadataParameters, data: &OutputData) -> Result<()> {
+    let id = DataId::from(id.to_string());
+    match data {
+        OutputData::Bytes(bytes) => node.send_output_bytes(id, parameters, bytes.len(), bytes),
+        OutputData::Array(array) => node.send_output(id, parameters, array.clone()),
+    }
+    .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// 输出发送方式：直接同步发送（默认行为），或经有界队列由后台线程发送，
+/// 下游变慢时按溢出策略阻塞或丢弃
+enum OutputSender {
+    Direct(DoraNode),
+    Queued {
+        queue: std::sync::Arc<SharedQueue<OutputMessage>>,
+        worker: std::thread::JoinHandle<()>,
+    },
+}
+
+impl OutputSender {
+    /// capacity 为 0 时直接发送
+    fn new(node: DoraNode, capacity: usize, policy: OverflowPolicy) -> Self {
+        if capacity == 0 {
+            return OutputSender::Direct(node);
+        }
+        eprintln!("Detector node: Output queue capacity {}, overflow policy {:?}", capacity, policy);
+        let queue = std::sync::Arc::new(SharedQueue::new(capacity, policy));
+        let worker_queue = queue.clone();
+        let worker = std::thread::spawn(move || {
+            let mut node = node;
+            while let Some(message) = worker_queue.pop() {
+                if let Err(e) = send_output_data(&mut node, &message.id, message.parameters, &message.data) {
+                    eprintln!("Detector node: Failed to send queued {} output: {}", message.id, e);
+                }
+            }
+        });
+        OutputSender::Queued { queue, worker }
+    }
+    
+    fn send(&mut self, id: &str, parameters: MetadataParameters, data: &[u8]) -> Result<()> {
+        match self {
+            OutputSender::Direct(node) => node
+                .send_output_bytes(DataId::from(id.to_string()), parameters, data.len(), data)
+                .map_err(|e| anyhow::anyhow!("{}", e)),
+            OutputSender::Queued { queue, .. } => {
+                Self::enqueue(queue, id, parameters, OutputData::Bytes(data.to_vec()));
+                Ok(())
+            }
+        }
+    }
+    
+    fn send_array(&mut self, id: &str, parameters: MetadataParameters, array: ArrayRef) -> Result<()> {
+        match self {
+            OutputSender::Direct(node) => send_output_data(node, id, parameters, &OutputData::Array(array)),
+            OutputSender::Queued { queue, .. } => {
+                Self::enqueue(queue, id, parameters, OutputData::Array(array));
+                Ok(())
+            }
+        }
+    }
+    
+    fn enqueue(queue: &SharedQueue<OutputMessage>, id: &str, parameters: MetadataParameters, data: OutputData) {
+        let outcome = queue.push(OutputMessage {
+            id: id.to_string(),
+            parameters,
+            data,
+        });
+        match outcome {
+            PushOutcome::Queued => {}
+            PushOutcome::DroppedOldest => eprintln!(
+                "Detector node: Output queue full, dropped oldest message ({} dropped in total)", queue.dropped()),
+            PushOutcome::DroppedNewest => eprintln!(
+                "Detector node: Output queue full, dropped new {} output ({} dropped in total)", id, queue.dropped()),
+        }
+    }
+    
+    /// 关闭队列并等待已排队的输出发送完毕
+    fn shutdown(self) {
+        if let OutputSender::Queued { queue, worker } = self {
+            queue.close();
+            if worker.join().is_err() {
+                eprintln!("Detector node: Output sender thread panicked");
+            }
+        }
+    }
+}
+
+/// 某一帧的检测结果快照
+#[derive(Debug, Clone)]
+struct DetectionSnapshot {
+    frame_id: u64,
+    timestamp_ms: u64,  // 帧采集时间戳（Unix毫秒），缺失时为处理时间
+    frame_width: u32,
+    frame_height: u32,
+    detections: Vec<DetectionRecord>,
+}
+
+/// 线程安全的最新检测结果句柄，可克隆后交给其他线程查询"当前检测到了什么"，
+/// 无需订阅 Dora 数据流
+#[derive(Clone, Default)]
+struct SnapshotHandle {
+    inner: std::sync::Arc<std::sync::Mutex<Option<DetectionSnapshot>>>,
+}
+
+impl SnapshotHandle {
+    /// 用最新处理完的帧替换快照
+    fn publish(&self, snapshot: DetectionSnapshot) {
+        if let Ok(mut guard) = self.inner.lock() {
+            *guard = Some(snapshot);
+        }
+    }
+    
+    /// 查询最新快照，尚未处理任何帧时返回 None
+    fn latest(&self) -> Option<DetectionSnapshot> {
+        self.inner.lock().ok().and_then(|guard| guard.clone())
+    }
+}
+
+/// 空闲计时器：距上次活动超过 interval 时触发（用于心跳重发、空闲保温推理），interval 为 0 表示关闭
+struct IdleTimer {
+    interval: Duration,
+    last_emit: std::time::Instant,
+}
+
+impl IdleTimer {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_emit: std::time::Instant::now(),
+        }
+    }
+    
+    fn enabled(&self) -> bool {
+        !self.interval.is_zero()
+    }
+    
+    fn is_due(&self, now: std::time::Instant) -> bool {
+        self.enabled() && now.duration_since(self.last_emit) >= self.interval
+    }
+    
+    fn mark(&mut self, now: std::time::Instant) {
+        self.last_emit = now;
+    }
+    
+    /// 事件等待超时不应超过计时间隔，否则触发会被延迟
+    fn recv_timeout(&self, default: Duration) -> Duration {
+        if self.enabled() { default.min(self.interval) } else { default }
+    }
+}
+
+/// 基于推理耗时的滞回模型切换器：
+/// 连续 window 帧耗时超过 high_ms 时切换到快速模型，连续 window 帧低于 low_ms 时切回精确模型
+struct ModelSwitcher {
+    high_ms: u64,
+    low_ms: u64,
+    window: u32,
+    over_count: u32,
+    under_count: u32,
+    use_fast: bool,
+}
+
+impl ModelSwitcher {
+    fn new(high_ms: u64, low_ms: u64, window: u32) -> Self {
+        Self {
+            high_ms,
+            low_ms: low_ms.min(high_ms),
+            window: window.max(1),
+            over_count: 0,
+            under_count: 0,
+            use_fast: false,
+        }
+    }
+    
+    /// 记录一次推理耗时，返回是否应使用快速模型
+    fn update(&mut self, elapsed_ms: u64) -> bool {
+        if elapsed_ms > self.high_ms {
+            self.over_count += 1;
+            self.under_count = 0;
+        } else if elapsed_ms < self.low_ms {
+            self.under_count += 1;
+            self.over_count = 0;
+        } else {
+            // 处于滞回区间内，保持当前模型
+            self.over_count = 0;
+            self.under_count = 0;
+        }
+        
+        if !self.use_fast && self.over_count >= self.window {
+            self.use_fast = true;
+            self.over_count = 0;
+        } else if self.use_fast && self.under_count >= self.window {
+            self.use_fast = false;
+            self.under_count = 0;
+        }
+        self.use_fast
+    }
+}
+
+const DEFAULT_MODEL_PATH: &str = "models/yolov8n.onnx";
+
+/// 检测节点命令行：不带子命令时作为 Dora 节点运行
+#[derive(Parser)]
+#[command(name = "detector_node", about = "YOLO detector node for Dora")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 对单张图片运行检测并打印结果
+    DetectImage {
+        /// 输入图片路径
+        path: String,
+        /// 模型路径
+        #[arg(long, default_value = DEFAULT_MODEL_PATH)]
+        model: String,
+        /// 将检测结果写入该文本文件
+        #[arg(long)]
+        output: Option<String>,
+        /// 模型缺失或加载失败时使用模拟检测结果，而不是报错
+        #[arg(long)]
+        mock: bool,
+    },
+    /// 在合成帧上运行内置基准测试
+    Bench {
+        /// 模型路径
+        #[arg(long, default_value = DEFAULT_MODEL_PATH)]
+        model: String,
+        /// 推理次数
+        #[arg(long, default_value_t = 20)]
+        iterations: u32,
+        /// 合成帧宽度
+        #[arg(long, default_value_t = 640)]
+        width: u32,
+        /// 合成帧高度
+        #[arg(long, default_value_t = 480)]
+        height: u32,
+        /// 模型缺失或加载失败时对模拟检测计时，而不是报错
+        #[arg(long)]
+        mock: bool,
+    },
+}
+
+fn main() -> Result<()> {
+    // 在最开始就初始化日志系统
+    env_logger::init();
+    
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::DetectImage { path, model, output, mock }) => run_detect_image(&path, &model, output.as_deref(), mock),
+        Some(Command::Bench { model, iterations, width, height, mock }) => run_bench(&model, iterations, width, height, mock),
+        None => run_node(),
+    }
+}
+
+/// 将 JPEG 帧解码为 BGR 像素，返回 (数据, 宽, 高)
+fn decode_jpeg_frame(bytes: &[u8]) -> Result<(Vec<u8>, u32, u32)> {
+    let buffer = opencv::core::Vector::<u8>::from_slice(bytes);
+    let image = opencv::imgcodecs::imdecode(&buffer, opencv::imgcodecs::IMREAD_COLOR)
+        .context("imdecode failed")?;
+    if image.empty() {
+        anyhow::bail!("{} bytes are not a decodable image", bytes.len());
+    }
+    let pixels = image.data_bytes().context("Decoded image is not continuous")?.to_vec();
+    Ok((pixels, image.cols() as u32, image.rows() as u32))
+}
+
+/// 创建子命令使用的检测器；模型缺失或加载失败时报错，除非显式允许模拟检测
+fn load_cli_detector(model_path: &str, mock: bool) -> Result<YoloDetector> {
+    let detector = YoloDetector::new(model_path)?;
+    if detector.model.is_none() {
+        if !mock {
+            anyhow::bail!("No model loaded from {}; pass --mock to run with mock detections", model_path);
+        }
+        eprintln!("Detector node: No model loaded from {}, using mock detections (--mock)", model_path);
+    }
+    Ok(detector)
+}
+
+/// 子命令 detect-image：读取图片、运行检测并打印（可选保存）结果
+fn run_detect_image(path: &str, model_path: &str, output: Option<&str>, mock: bool) -> Result<()> {
+    let lines = detect_image_lines(path, model_path, mock)?;
+    println!("Detected {} objects in {}", lines.len(), path);
+    for line in &lines {
+        println!("{}", line);
+    }
+    
+    if let Some(output) = output {
+        std::fs::write(output, lines.join("\n") + "\n")
+            .with_context(|| format!("Failed to write results to {}", output))?;
+        println!("Results saved to {}", output);
+    }
+    Ok(())
+}
+
+/// 读取图片并运行检测，返回 `class conf x y w h` 格式的结果行
+fn detect_image_lines(path: &str, model_path: &str, mock: bool) -> Result<Vec<String>> {
+    let image = opencv::imgcodecs::imread(path, opencv::imgcodecs::IMREAD_COLOR)
+        .with_context(|| format!("Failed to read image {}", path))?;
+    if image.empty() {
+        anyhow::bail!("Failed to decode image {}", path);
+    }
+    let size = image.size().context("Failed to get image size")?;
+    let img_data = image.data_bytes().context("Image data is not continuous")?.to_vec();
+    
+    let detector = load_cli_detector(model_path, mock)?;
+    let detections = detector.detect(&img_data, size.width as u32, size.height as u32)?;
+    
+    Ok(detections.iter().map(detection_msg::to_text_line).collect())
+}
+
+/// 子命令 bench：在合成帧上重复推理并打印耗时统计
+fn run_bench(model_path: &str, iterations: u32, width: u32, height: u32, mock: bool) -> Result<()> {
+    let detector = load_cli_detector(model_path, mock)?;
+    let img_data = vec![114u8; (width * height * 3) as usize];
+    
+    // 预热一次，避免首帧冷启动影响统计
+    detector.detect(&img_data, width, height)?;
+    
+    let mut timings_ms = Vec::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+        let start = std::time::Instant::now();
+        detector.detect(&img_data, width, height)?;
+        timings_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+    }
+    
+    let count = timings_ms.len().max(1) as f64;
+    let mean = timings_ms.iter().sum::<f64>() / count;
+    let min = timings_ms.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = timings_ms.iter().cloned().fold(0.0, f64::max);
+    println!("Benchmark: {} iterations on {}x{} frame", iterations, width, height);
+    println!("  mean {:.2} ms, min {:.2} ms, max {:.2} ms, ~{:.1} FPS", mean, min, max, 1000.0 / mean);
+    Ok(())
+}
+
+fn run_node() -> Result<()> {
+    // 立即打印启动信息
+    println!("Detector node: Starting... (stdout)");
+    eprintln!("Detector node: Starting... (stderr)");
+    eprintln!("Detector node: Starting... (info)");
+    
+    let (node, mut event_stream) = match DoraNode::init_from_env() {
+        Ok(n) => {
+            eprintln!("Detector node: Dora node initialized successfully");
+            n
+        },
+        Err(e) => {
+            eprintln!("Detector node: Failed to initialize DoraNode: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // 输出发送方式：默认直接发送；配置队列容量后经有界队列由后台线程发送
+    let mut outputs = OutputSender::new(
+        node,
+        env_or("YOLO_OUTPUT_QUEUE_CAPACITY", 0),
+        env_or("YOLO_OUTPUT_OVERFLOW", OverflowPolicy::Block),
+    );
+
+    // 初始化YOLO检测器
+    let model_path: String = env_or("YOLO_MODEL_PATH", DEFAULT_MODEL_PATH.to_string());
+    let mut detector = match YoloDetector::new(&model_path) {
+        Ok(d) => {
+            eprintln!("Detector node: YOLO detector initialized");
+            d
+        },
+        Err(e) => {
+            eprintln!("Detector node: Failed to initialize YOLO detector: {}", e);
+            return Err(e);
+        }
+    };
+
+    // 启动自测：处理真实数据前先报告本机的预期性能
+    if matches!(std::env::var("YOLO_SELFPROFILE").as_deref(), Ok("1") | Ok("true")) {
+        let iterations: u32 = env_or("YOLO_SELFPROFILE_ITERATIONS", 20);
+        if let Err(e) = run_self_profile(&detector, iterations, 640, 480) {
+            eprintln!("Detector node: Self-profile failed: {:#}", e);
+        }
+    }
+
+    let mut frame_counter: u64 = 0;
+    eprintln!("Detector node: Ready to receive data");
+
+    // 帧新鲜度预算：采集时间戳早于该值（毫秒）的帧直接丢弃，0 表示不限制
+    let max_frame_age_ms: u64 = env_or("YOLO_MAX_FRAME_AGE_MS", 0);
+    let mut dropped_stale: u64 = 0;
+    // 上游丢帧统计
+    let mut frame_gaps = FrameGapTracker::new();
+    if max_frame_age_ms > 0 {
+        eprintln!("Detector node: Dropping frames older than {} ms", max_frame_age_ms);
+    }
+
+    // 自适应跳帧机制
+    let mut skip_counter = 0;
+    let mut process_interval = 1; // 初始为每帧都处理
+    // 关闭自适应跳帧后每一帧都做检测
+    let adaptive_skip: bool = env_or("YOLO_ADAPTIVE_SKIP", true);
+
+    // 可视化抽帧：检测结果每帧发送，转发给可视化的帧每 N 帧发送一次
+    let visual_decimation: u64 = env_or("YOLO_VISUAL_DECIMATION", 1);
+    if visual_decimation > 1 {
+        eprintln!("Detector node: Forwarding every {} processed frame(s) for visualization", visual_decimation);
+    }
+    // 关闭转发后不再输出 frame，避免重复拷贝整帧图像，可视化节点应直接订阅 camera/frame
+    let frame_passthrough: bool = env_or("YOLO_FRAME_PASSTHROUGH", true);
+    if !frame_passthrough {
+        eprintln!("Detector node: Frame passthrough disabled, subscribe the visualizer to camera/frame");
+    }
+
+    // 定长输出模式：每帧恰好发送 max_detections 条记录，不足部分用哨兵记录补齐
+    let fixed_records: bool = env_or("YOLO_FIXED_RECORDS", false);
+    let max_detections: usize = env_or("YOLO_MAX_DETECTIONS", 100);
+    // 输出前的最终置信度下限，独立于可调的检测阈值
+    let mut conf_floor = env_unit_interval("YOLO_CONF_FLOOR", 0.0);
+    // 类别分组计数（如 vehicle=car+truck+bus），载荷中仍保留原始类别
+    let class_groups = ClassGroups::from_env("YOLO_CLASS_GROUPS");
+    // 输出前的排序方式
+    let sort_order: SortOrder = env_or("YOLO_SORT", SortOrder::Confidence);
+    // 同类近邻聚类：中心点归一化距离不超过该值的同类检测归为一簇，0 表示关闭
+    let cluster_distance: f32 = env_or("YOLO_CLUSTER_DISTANCE", 0.0);
+    // 额外的 top_detection 输出：每帧只发送置信度最高的一个目标
+    let top_detection_output: bool = env_or("YOLO_TOP_DETECTION", false);
+
+    // 可选的 syslog 检测事件输出（无头服务器上接入常规日志系统）
+    let mut log_sink = match DetectionLogSink::from_env() {
+        Ok(sink) => sink,
+        Err(e) => {
+            eprintln!("Detector node: Failed to open syslog sink, disabling it: {:#}", e);
+            None
+        }
+    };
+
+    // 可选的 CSV 检测结果导出
+    let mut csv_exporter = match std::env::var("YOLO_CSV_PATH") {
+        Ok(path) => match CsvExporter::open(
+            &path,
+            env_or("YOLO_CSV_MAX_BYTES", 0),
+            Duration::from_secs(env_or("YOLO_CSV_ROTATE_SECS", 0)),
+        ) {
+            Ok(exporter) => {
+                eprintln!("Detector node: Appending detections to CSV file {}", path);
+                Some(exporter)
+            }
+            Err(e) => {
+                eprintln!("Detector node: Failed to open CSV file, disabling export: {:#}", e);
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
+    // 按负载切换模型分辨率（需要配置 YOLO_FAST_MODEL_PATH）
+    let mut model_switcher = ModelSwitcher::new(
+        env_or("YOLO_SWITCH_HIGH_MS", 120),
+        env_or("YOLO_SWITCH_LOW_MS", 60),
+        env_or("YOLO_SWITCH_WINDOW", 5),
+    );
+
+    // 心跳：输入帧停滞时按间隔重发最近一次检测结果（或"无数据"标记），0 表示关闭
+    let mut heartbeat = IdleTimer::new(Duration::from_millis(env_or("YOLO_HEARTBEAT_MS", 0)));
+    // 最新检测结果快照，可供进程内其他组件（如内嵌 HTTP/gRPC 服务）查询
+    let snapshot = SnapshotHandle::default();
+
+    // 空闲保温：长时间没有输入帧时周期性地做一次空推理，避免缓存被换出导致恢复后的首帧变慢
+    let keepalive_idle_ms: u64 = if env_or("YOLO_KEEPALIVE", false) { env_or("YOLO_KEEPALIVE_IDLE_MS", 5000) } else { 0 };
+    let mut keepalive = IdleTimer::new(Duration::from_millis(keepalive_idle_ms));
+    // 帧看门狗：超时未收到帧时以非零码退出，交给监管进程重启
+    let mut watchdog = FrameWatchdog::from_env(std::time::Instant::now());
+    if let Some(timeout) = watchdog.timeout() {
+        eprintln!("Detector node: Frame watchdog enabled, exiting if no frame arrives within {:?}", timeout);
+    }
+    let recv_timeout = watchdog.recv_timeout(keepalive.recv_timeout(heartbeat.recv_timeout(Duration::from_millis(1000))));
+
+    loop {
+        if watchdog.expired(std::time::Instant::now()) {
+            eprintln!("Detector node: No frame received within {:?}, exiting for restart", watchdog.timeout().unwrap_or_default());
+            outputs.shutdown();
+            std::process::exit(2);
+        }
+        if heartbeat.is_due(std::time::Instant::now()) {
+            let mut parameters = MetadataParameters::new();
+            parameters.insert("heartbeat".to_string(), dora_node_api::Parameter::String("true".to_string()));
+            let detection_array = match snapshot.latest() {
+                Some(latest) => {
+                    parameters.insert("num_detections".to_string(), dora_node_api::Parameter::String(latest.detections.len().to_string()));
+                    parameters.insert("frame_id".to_string(), dora_node_api::Parameter::String(latest.frame_id.to_string()));
+                    parameters.insert("frame_width".to_string(), dora_node_api::Parameter::String(latest.frame_width.to_string()));
+                    parameters.insert("frame_height".to_string(), dora_node_api::Parameter::String(latest.frame_height.to_string()));
+                    parameters.insert("timestamp_ms".to_string(), dora_node_api::Parameter::String(latest.timestamp_ms.to_string()));
+                    detection_msg::to_arrow(&latest.detections)
+                }
+                None => {
+                    parameters.insert("num_detections".to_string(), dora_node_api::Parameter::String("0".to_string()));
+                    parameters.insert("no_data".to_string(), dora_node_api::Parameter::String("true".to_string()));
+                    detection_msg::to_arrow(&[])
+                }
+            };
+            eprintln!("Detector node: No frames for {:?}, sending heartbeat", heartbeat.interval);
+            if let Err(e) = outputs.send_array("detections", parameters, detection_array) {
+                eprintln!("Detector node: Failed to send heartbeat: {}", e);
+            }
+            heartbeat.mark(std::time::Instant::now());
+        }
+        
+        if keepalive.is_due(std::time::Instant::now()) {
+            let start = std::time::Instant::now();
+            match detector.warm_up() {
+                Ok(()) => eprintln!("Detector node: Idle keep-alive inference took {} ms", start.elapsed().as_millis()),
+                Err(e) => eprintln!("Detector node: Idle keep-alive inference failed: {:#}", e),
+            }
+            keepalive.mark(std::time::Instant::now());
+        }
+        
+        // 添加调试日志，查看是否能接收到任何事件
+        eprintln!("Detector node: Waiting for event...");
+        
+        if let Some(event) = event_stream.recv_timeout(recv_timeout) {
+            eprintln!("Detector node: Received an event");
+            
+            match event {
+                Event::Input { id, data, metadata } => {
+                    eprintln!("Detector node: Received input with id '{}'", id);
+                    
+                    // 打印所有元数据参数，帮助调试
+                    eprintln!("Detector node: Metadata parameters: {:?}", metadata.parameters);
+                    
+                    if id.as_str() == "frame" || id.as_str() == "tensor" {
+                        eprintln!("Detector node: Processing frame input with id '{}'", id);
+                        keepalive.mark(std::time::Instant::now());
+                        watchdog.reset(std::time::Instant::now());
+                        
+                        // 从元数据中获取图像尺寸 - 使用更灵活的方式
+                        let mut width = match metadata.parameters.get("width") {
+                            Some(dora_node_api::Parameter::String(s)) => s.parse::<u32>().ok().unwrap_or(640),
                             Some(dora_node_api::Parameter::Integer(i)) => *i as u32,
                             _ => {
                                 // 如果没有元数据，尝试根据数据大小推断
@@ -333,7 +1903,7 @@ fn main() -> Result<()> {
                                 }
                             }
                         };
-                        let height = match metadata.parameters.get("height") {
+                        let mut height = match metadata.parameters.get("height") {
                             Some(dora_node_api::Parameter::String(s)) => s.parse::<u32>().ok().unwrap_or(480),
                             Some(dora_node_api::Parameter::Integer(i)) => *i as u32,
                             _ => {
@@ -349,13 +1919,78 @@ fn main() -> Result<()> {
                         };
                         
                         eprintln!("Detector node: Image dimensions - {}x{}", width, height);
+
+                        // 检测上游丢帧（frame_id 不连续）
+                        if let Some(source_id) = param_u64(&metadata.parameters, "frame_id") {
+                            if let Some(missing) = frame_gaps.observe(source_id as u32) {
+                                eprintln!("Detector node: Frame id gap before {}: {} frame(s) missing upstream, {} dropped in total",
+                                         source_id, missing, frame_gaps.dropped_frames());
+                            }
+                        }
+
+                        // 过期帧丢弃：积压时不在陈旧数据上做推理
+                        let frame_timestamp_ms = param_u64(&metadata.parameters, "timestamp_ms");
+                        if let Some(timestamp_ms) = frame_timestamp_ms {
+                            if is_frame_stale(timestamp_ms, now_millis(), max_frame_age_ms) {
+                                dropped_stale += 1;
+                                eprintln!("Detector node: Dropping stale frame (age {} ms > {} ms), dropped {} stale frames so far",
+                                         now_millis().saturating_sub(timestamp_ms), max_frame_age_ms, dropped_stale);
+                                continue;
+                            }
+                        }
                         
-                        // 获取图像数据
-                        let array = data.as_any().downcast_ref::<UInt8Array>()
-                            .context("Expected UInt8Array")?;
-                        let img_data: Vec<u8> = array.iter().filter_map(|x| x).collect();
-                        
-                        eprintln!("Detector node: Received frame data with {} bytes", img_data.len());
+                        // 获取图像数据（或预处理好的张量）；JPEG 帧解码后检测，转发时仍发送原 JPEG 数据
+                        let mut encoded_frame: Option<Vec<u8>> = None;
+                        let input = if id.as_str() == "tensor" {
+                            let values = match tensor_values(&**data) {
+                                Ok(values) => values,
+                                Err(e) => {
+                                    eprintln!("Detector node: {:#}, skipping", e);
+                                    continue;
+                                }
+                            };
+                            let shape = match metadata.parameters.get("shape") {
+                                Some(dora_node_api::Parameter::String(s)) => parse_shape(s),
+                                _ => None,
+                            };
+                            let geometry = match TensorGeometry::from_parameters(&metadata.parameters) {
+                                Ok(geometry) => geometry,
+                                Err(e) => {
+                                    eprintln!("Detector node: Invalid tensor geometry metadata, skipping: {:#}", e);
+                                    continue;
+                                }
+                            };
+                            eprintln!("Detector node: Received tensor with {} values, shape {:?}, geometry {:?}", values.len(), shape, geometry);
+                            FrameInput::Tensor { values, shape, geometry }
+                        } else {
+                            let array = data.as_any().downcast_ref::<UInt8Array>()
+                                .context("Expected UInt8Array")?;
+                            let img_data: Vec<u8> = array.iter().filter_map(|x| x).collect();
+                            eprintln!("Detector node: Received frame data with {} bytes", img_data.len());
+                            let encoding = match metadata.parameters.get(ENCODING_PARAM) {
+                                Some(dora_node_api::Parameter::String(s)) => s.parse::<FrameEncoding>(),
+                                _ => Ok(FrameEncoding::Raw),
+                            };
+                            match encoding {
+                                Ok(FrameEncoding::Raw) => FrameInput::Image(img_data),
+                                Ok(FrameEncoding::Jpeg) => match decode_jpeg_frame(&img_data) {
+                                    Ok((pixels, decoded_width, decoded_height)) => {
+                                        width = decoded_width;
+                                        height = decoded_height;
+                                        encoded_frame = Some(img_data);
+                                        FrameInput::Image(pixels)
+                                    }
+                                    Err(e) => {
+                                        eprintln!("Detector node: Failed to decode JPEG frame, skipping: {:#}", e);
+                                        continue;
+                                    }
+                                },
+                                Err(e) => {
+                                    eprintln!("Detector node: {}, skipping frame", e);
+                                    continue;
+                                }
+                            }
+                        };
                         
                         // 自适应跳帧：根据处理时间调整处理间隔
                         let should_process = skip_counter % process_interval == 0;
@@ -364,7 +1999,21 @@ fn main() -> Result<()> {
                             let start_time = std::time::Instant::now();
                             
                             // 运行检测
-                            let detections = detector.detect(&img_data, width, height)?;
+                            let result = match &input {
+                                FrameInput::Image(img_data) => detector.detect(img_data, width, height),
+                                FrameInput::Tensor { values, shape, geometry } => detector.detect_tensor(values, shape.as_deref(), *geometry, width, height),
+                            };
+                            let detections = match result {
+                                Ok(d) => d,
+                                Err(e) => {
+                                    eprintln!("Detector node: Detection failed, skipping frame: {:#}", e);
+                                    skip_counter += 1;
+                                    continue;
+                                }
+                            };
+                            // 硬性置信度下限：低于该值的检测在任何配置下都不会输出
+                            let mut detections = apply_confidence_floor(detections, conf_floor);
+                            sort_detections(&mut detections, sort_order);
                             
                             // 计算处理时间并调整跳帧间隔
                             let elapsed = start_time.elapsed();
@@ -372,8 +2021,20 @@ fn main() -> Result<()> {
                             
                             eprintln!("Detector node: Detection took {} ms", elapsed_ms);
                             
+                            // 根据推理耗时滞回切换模型分辨率
+                            if detector.can_switch_resolution() {
+                                let use_fast = model_switcher.update(elapsed_ms);
+                                if use_fast != detector.use_fast_model {
+                                    detector.set_fast_mode(use_fast);
+                                    eprintln!("Detector node: Switched to {} model",
+                                             if use_fast { "fast (small input)" } else { "accurate (large input)" });
+                                }
+                            }
+                            
                             // 根据处理时间自适应调整跳帧间隔
-                            if elapsed_ms > 150 { // 如果处理时间超过150ms
+                            if !adaptive_skip {
+                                // 自适应跳帧已关闭，保持逐帧处理
+                            } else if elapsed_ms > 150 { // 如果处理时间超过150ms
                                 process_interval = std::cmp::min(process_interval + 1, 10); // 最多跳过9帧
                                 eprintln!("Detector node: Increased process interval to {}", process_interval);
                             } else if elapsed_ms < 50 && process_interval > 1 { // 如果处理很快且当前间隔大于1
@@ -381,53 +2042,88 @@ fn main() -> Result<()> {
                                 eprintln!("Detector node: Decreased process interval to {}", process_interval);
                             }
                             
-                            // 将检测结果序列化
-                            let mut detection_bytes = Vec::new();
-                            for detection in &detections {
-                                // 序列化name字段（16字节固定长度）
-                                let name_bytes = detection.name.as_bytes();
-                                let name_len = name_bytes.len().min(16);
-                                detection_bytes.extend_from_slice(&name_bytes[..name_len]);
-                                detection_bytes.extend_from_slice(&vec![0; 16 - name_len]);
-                                
-                                // 序列化class_name（16字节固定长度）
-                                let class_bytes = detection.class_name.as_bytes();
-                                let class_len = class_bytes.len().min(16);
-                                detection_bytes.extend_from_slice(&class_bytes[..class_len]);
-                                detection_bytes.extend_from_slice(&vec![0; 16 - class_len]);
-                                
-                                // 序列化其他数值
-                                detection_bytes.extend_from_slice(&detection.confidence.to_le_bytes());
-                                detection_bytes.extend_from_slice(&detection.x.to_le_bytes());
-                                detection_bytes.extend_from_slice(&detection.y.to_le_bytes());
-                                detection_bytes.extend_from_slice(&detection.width.to_le_bytes());
-                                detection_bytes.extend_from_slice(&detection.height.to_le_bytes());
-                            }
+                            // 将检测结果编码为 Arrow 数组（定长模式下补齐哨兵记录）
+                            let detection_array = if fixed_records {
+                                detection_msg::to_arrow(&pad_to_fixed_records(&detections, max_detections))
+                            } else {
+                                detection_msg::to_arrow(&detections)
+                            };
+                            
+                            let (frame_width, frame_height) = source_dimensions(&metadata.parameters, width, height);
                             
                             // 发送检测结果
-                            let output_id = DataId::from("detections".to_string());
                             let mut parameters = MetadataParameters::new();
                             parameters.insert("num_detections".to_string(), dora_node_api::Parameter::String(detections.len().to_string()));
                             parameters.insert("frame_id".to_string(), dora_node_api::Parameter::String(frame_counter.to_string()));
+                            parameters.insert("dropped_frames".to_string(), dora_node_api::Parameter::String(frame_gaps.dropped_frames().to_string()));
+                            // 源图像尺寸，供只订阅检测结果的下游将归一化坐标换算为像素
+                            parameters.insert("frame_width".to_string(), dora_node_api::Parameter::String(frame_width.to_string()));
+                            parameters.insert("frame_height".to_string(), dora_node_api::Parameter::String(frame_height.to_string()));
+                            if !class_groups.is_empty() {
+                                let counts: Vec<String> = class_groups.count(detections.iter().map(|d| d.class_name.as_str()))
+                                    .into_iter()
+                                    .map(|(group, count)| format!("{}:{}", group, count))
+                                    .collect();
+                                parameters.insert("group_counts".to_string(), dora_node_api::Parameter::String(counts.join(",")));
+                            }
+                            // 聚类结果：簇数量及每簇的 "类别:成员数"，用于估计密集目标的数量
+                            if cluster_distance > 0.0 {
+                                let clusters = cluster_detections(&detections, cluster_distance);
+                                let summary: Vec<String> = clusters.iter()
+                                    .map(|c| format!("{}:{}", detections[c[0]].class_name, c.len()))
+                                    .collect();
+                                parameters.insert("cluster_count".to_string(), dora_node_api::Parameter::String(clusters.len().to_string()));
+                                parameters.insert("clusters".to_string(), dora_node_api::Parameter::String(summary.join(",")));
+                            }
                             
-                            if let Err(e) = node.send_output_bytes(
-                                output_id,
-                                parameters.clone(),
-                                detection_bytes.len(),
-                                &detection_bytes
-                            ) {
+                            if let Err(e) = outputs.send_array("detections", parameters.clone(), detection_array) {
                                 eprintln!("Detector node: Failed to send detections output: {}", e);
                             }
+                            if top_detection_output {
+                                let top = top_detection(&detections).cloned().unwrap_or_default();
+                                if let Err(e) = outputs.send_array("top_detection", parameters, detection_msg::to_arrow(&[top])) {
+                                    eprintln!("Detector node: Failed to send top_detection output: {}", e);
+                                }
+                            }
+                            heartbeat.mark(std::time::Instant::now());
+                            snapshot.publish(DetectionSnapshot {
+                                frame_id: frame_counter,
+                                timestamp_ms: frame_timestamp_ms.unwrap_or_else(now_millis),
+                                frame_width,
+                                frame_height,
+                                detections: detections.clone(),
+                            });
                             
-                            // 转发原始帧
-                            let output_id = DataId::from("frame".to_string());
-                            if let Err(e) = node.send_output_bytes(
-                                output_id,
-                                parameters,
-                                img_data.len(),
-                                &img_data
-                            ) {
-                                eprintln!("Detector node: Failed to send frame output: {}", e);
+                            // 转发原始帧（按可视化抽帧间隔，张量输入没有可转发的图像）
+                            let forward = should_forward_frame(frame_passthrough, frame_counter, visual_decimation);
+                            if let (FrameInput::Image(img_data), true) = (&input, forward) {
+                                // 转发帧带源帧自身的元数据（frame_id 保持上游编号），而不是检测结果的参数
+                                let mut frame_parameters = MetadataParameters::new();
+                                for key in ["frame_id", "timestamp_ms", "channels", "source_width", "source_height"] {
+                                    if let Some(value) = metadata.parameters.get(key) {
+                                        frame_parameters.insert(key.to_string(), value.clone());
+                                    }
+                                }
+                                frame_parameters.insert("width".to_string(), dora_node_api::Parameter::String(width.to_string()));
+                                frame_parameters.insert("height".to_string(), dora_node_api::Parameter::String(height.to_string()));
+                                let (frame_data, frame_encoding) = match &encoded_frame {
+                                    Some(jpeg) => (jpeg, FrameEncoding::Jpeg),
+                                    None => (img_data, FrameEncoding::Raw),
+                                };
+                                frame_parameters.insert(ENCODING_PARAM.to_string(), dora_node_api::Parameter::String(frame_encoding.as_str().to_string()));
+                                if let Err(e) = outputs.send("frame", frame_parameters, frame_data) {
+                                    eprintln!("Detector node: Failed to send frame output: {}", e);
+                                }
+                            }
+                            
+                            if let Some(sink) = log_sink.as_mut() {
+                                sink.log(frame_counter, &detections);
+                            }
+                            if let Some(exporter) = csv_exporter.as_mut() {
+                                let timestamp_ms = frame_timestamp_ms.unwrap_or_else(now_millis);
+                                if let Err(e) = exporter.write_frame(frame_counter, timestamp_ms, &detections) {
+                                    eprintln!("Detector node: Failed to write CSV rows: {:#}", e);
+                                }
                             }
                             
                             frame_counter += 1;
@@ -439,8 +2135,21 @@ fn main() -> Result<()> {
                         }
                         
                         skip_counter += 1;
+                    } else if id.as_str() == "config" {
+                        // 运行时阈值调整，下一帧立即生效
+                        let Some(array) = data.as_any().downcast_ref::<UInt8Array>() else {
+                            eprintln!("Detector node: Expected UInt8Array on 'config' input, skipping");
+                            continue;
+                        };
+                        let bytes: Vec<u8> = array.iter().filter_map(|x| x).collect();
+                        for (key, value) in parse_config_updates(&String::from_utf8_lossy(&bytes)) {
+                            match apply_threshold_update(&mut detector, &mut conf_floor, &key, &value) {
+                                Ok(()) => eprintln!("Detector node: Config updated: {} = {}", key, value),
+                                Err(e) => eprintln!("Detector node: Rejected config update {}={}: {}", key, value, e),
+                            }
+                        }
                     } else {
-                        eprintln!("Detector node: Received input with id '{}' but expected 'frame'", id);
+                        eprintln!("Detector node: Received input with id '{}' but expected 'frame', 'tensor' or 'config'", id);
                     }
                 }
                 Event::Stop(_) => {
@@ -456,19 +2165,444 @@ fn main() -> Result<()> {
                     eprintln!("Detector node: Received unhandled event type: {:?}", event);
                 }
             }
-        } else {
-            // 没有收到事件，继续循环
-            eprintln!("Detector node: No events received in timeout period");
+        } else {
+            // 没有收到事件，继续循环
+            eprintln!("Detector node: No events received in timeout period");
+        }
+    }
+
+    outputs.shutdown();
+    eprintln!("Detector node: Finished, dropped {} stale frames, {} frames missing upstream",
+             dropped_stale, frame_gaps.dropped_frames());
+    Ok(())
+}
+
+/// 将检测结果以结构化摘要写入 syslog（systemd 环境下会进入 journald），按最小间隔限速
+struct DetectionLogSink {
+    logger: syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>,
+    rate_limit: RateLimit,
+}
+
+/// 最小间隔限速：距上一次放行不足 min_interval 时拒绝
+struct RateLimit {
+    min_interval: Duration,
+    last_emit: Option<std::time::Instant>,
+}
+
+impl RateLimit {
+    fn new(min_interval: Duration) -> Self {
+        Self { min_interval, last_emit: None }
+    }
+    
+    /// 是否允许在 now 时刻发出一条事件，放行时记录时间
+    fn allow(&mut self, now: std::time::Instant) -> bool {
+        if self.last_emit.is_some_and(|last| now.duration_since(last) < self.min_interval) {
+            return false;
+        }
+        self.last_emit = Some(now);
+        true
+    }
+}
+
+impl DetectionLogSink {
+    /// 根据 YOLO_LOG_SINK / YOLO_SYSLOG_TARGET 创建，未启用时返回 None
+    fn from_env() -> Result<Option<Self>> {
+        let sink: String = env_or("YOLO_LOG_SINK", "none".to_string());
+        if sink != "syslog" {
+            return Ok(None);
+        }
+        
+        let formatter = syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_USER,
+            hostname: None,
+            process: "detector_node".into(),
+            pid: std::process::id(),
+        };
+        // 目标：local（本机 /dev/log）、udp:host:port 或 tcp:host:port
+        let target: String = env_or("YOLO_SYSLOG_TARGET", "local".to_string());
+        let logger = if let Some(server) = target.strip_prefix("udp:") {
+            syslog::udp(formatter, "0.0.0.0:0", server)
+        } else if let Some(server) = target.strip_prefix("tcp:") {
+            syslog::tcp(formatter, server)
+        } else {
+            syslog::unix(formatter)
+        }.map_err(|e| anyhow::anyhow!("syslog target '{}': {}", target, e))?;
+        
+        let min_interval_ms: u64 = env_or("YOLO_SYSLOG_MIN_INTERVAL_MS", 1000);
+        eprintln!("Detector node: Logging detections to syslog ({}), at most one event per {} ms", target, min_interval_ms);
+        Ok(Some(Self {
+            logger,
+            rate_limit: RateLimit::new(Duration::from_millis(min_interval_ms)),
+        }))
+    }
+    
+    /// 记录一帧的检测结果，无检测或未到限速间隔时跳过
+    fn log(&mut self, frame_id: u64, detections: &[DetectionRecord]) {
+        if detections.is_empty() || !self.rate_limit.allow(std::time::Instant::now()) {
+            return;
+        }
+        if let Err(e) = self.logger.info(format_detection_log_line(frame_id, detections)) {
+            eprintln!("Detector node: Failed to write syslog event: {}", e);
+        }
+    }
+}
+
+/// 构造一帧检测结果的日志行，例如：
+/// `frame_id=12 count=2 person:0.95 car:0.87`
+fn format_detection_log_line(frame_id: u64, detections: &[DetectionRecord]) -> String {
+    let mut line = format!("frame_id={} count={}", frame_id, detections.len());
+    for detection in detections {
+        line.push_str(&format!(" {}:{:.2}", detection.class_name.replace(' ', "_"), detection.confidence));
+    }
+    line
+}
+
+const CSV_HEADER: &str = "frame_id,timestamp,class_name,confidence,x,y,w,h";
+
+/// 以 CSV 追加写入检测结果，每条检测一行；按文件大小或时间轮转
+struct CsvExporter {
+    path: std::path::PathBuf,
+    writer: std::io::BufWriter<std::fs::File>,
+    bytes_written: u64,
+    opened_at: std::time::Instant,
+    max_bytes: u64,           // 0 表示不按大小轮转
+    rotate_after: Duration,   // 0 表示不按时间轮转
+}
+
+impl CsvExporter {
+    fn open(path: &str, max_bytes: u64, rotate_after: Duration) -> Result<Self> {
+        let path = std::path::PathBuf::from(path);
+        let (writer, bytes_written) = Self::open_file(&path)?;
+        Ok(Self {
+            path,
+            writer,
+            bytes_written,
+            opened_at: std::time::Instant::now(),
+            max_bytes,
+            rotate_after,
+        })
+    }
+    
+    /// 以追加方式打开文件，新文件（或空文件）写入表头
+    fn open_file(path: &Path) -> Result<(std::io::BufWriter<std::fs::File>, u64)> {
+        use std::io::Write;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut bytes_written = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut writer = std::io::BufWriter::new(file);
+        if bytes_written == 0 {
+            writeln!(writer, "{}", CSV_HEADER)?;
+            bytes_written = CSV_HEADER.len() as u64 + 1;
+        }
+        Ok((writer, bytes_written))
+    }
+    
+    fn write_frame(&mut self, frame_id: u64, timestamp_ms: u64, detections: &[DetectionRecord]) -> Result<()> {
+        use std::io::Write;
+        if self.should_rotate() {
+            self.rotate()?;
+        }
+        for row in format_csv_rows(frame_id, timestamp_ms, detections) {
+            writeln!(self.writer, "{}", row)?;
+            self.bytes_written += row.len() as u64 + 1;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+    
+    fn should_rotate(&self) -> bool {
+        (self.max_bytes > 0 && self.bytes_written >= self.max_bytes)
+            || (!self.rotate_after.is_zero() && self.opened_at.elapsed() >= self.rotate_after)
+    }
+    
+    /// 将当前文件重命名为 <path>.<unix毫秒> 并重新打开一个新文件
+    fn rotate(&mut self) -> Result<()> {
+        use std::io::Write;
+        self.writer.flush()?;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", now_millis()));
+        std::fs::rename(&self.path, &rotated)
+            .with_context(|| format!("Failed to rotate {}", self.path.display()))?;
+        let (writer, bytes_written) = Self::open_file(&self.path)?;
+        self.writer = writer;
+        self.bytes_written = bytes_written;
+        self.opened_at = std::time::Instant::now();
+        Ok(())
+    }
+}
+
+/// 生成一帧检测结果对应的 CSV 行（不含表头）
+fn format_csv_rows(frame_id: u64, timestamp_ms: u64, detections: &[DetectionRecord]) -> Vec<String> {
+    detections.iter().map(|d| {
+        format!("{},{},{},{:.4},{:.6},{:.6},{:.6},{:.6}",
+                frame_id, timestamp_ms, csv_escape(&d.class_name), d.confidence, d.x, d.y, d.width, d.height)
+    }).collect()
+}
+
+/// 按 RFC 4180 转义字段：包含逗号、引号或换行时加引号，并将引号加倍
+fn csv_escape(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') || field.contains('\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// 读取环境变量并解析，缺失时使用默认值，非法时打印警告并回退到默认值
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => match value.trim().parse::<T>() {
+            Ok(v) => v,
+            Err(_) => {
+                eprintln!("Detector node: Invalid value '{}' for {}, using default", value, key);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+/// 读取取值须在 [0, 1] 内的阈值，缺失时使用默认值；非数字、NaN 或超出范围时与 env_or 一样打印警告并回退到默认值
+fn env_unit_interval(key: &str, default: f32) -> f32 {
+    let Ok(value) = std::env::var(key) else {
+        return default;
+    };
+    match value.trim().parse::<f32>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => v,
+        _ => {
+            eprintln!("Detector node: Invalid value '{}' for {} (expected a number in [0, 1]), using default", value, key);
+            default
+        }
+    }
+}
+
+/// 从元数据参数中读取无符号整数（兼容字符串和整数两种写法）
+fn param_u64(parameters: &MetadataParameters, key: &str) -> Option<u64> {
+    match parameters.get(key) {
+        Some(dora_node_api::Parameter::String(s)) => s.parse::<u64>().ok(),
+        Some(dora_node_api::Parameter::Integer(i)) => u64::try_from(*i).ok(),
+        _ => None,
+    }
+}
+
+/// 源图像尺寸：上游发送缩小帧时由 source_width/source_height 给出，缺失时即为当前帧尺寸
+fn source_dimensions(parameters: &MetadataParameters, width: u32, height: u32) -> (u32, u32) {
+    (
+        param_u64(parameters, "source_width").map_or(width, |w| w as u32),
+        param_u64(parameters, "source_height").map_or(height, |h| h as u32),
+    )
+}
+
+/// 将同类且中心点距离不超过 max_distance 的检测按单链接方式聚成簇，
+/// 返回每簇成员在 detections 中的下标（簇内下标升序，簇按首个成员排序）
+fn cluster_detections(detections: &[DetectionRecord], max_distance: f32) -> Vec<Vec<usize>> {
+    // 并查集
+    let mut parent: Vec<usize> = (0..detections.len()).collect();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        let mut node = i;
+        while parent[node] != root {
+            let next = parent[node];
+            parent[node] = root;
+            node = next;
+        }
+        root
+    }
+    
+    for i in 0..detections.len() {
+        for j in (i + 1)..detections.len() {
+            let (a, b) = (&detections[i], &detections[j]);
+            if a.class_name != b.class_name {
+                continue;
+            }
+            let distance = ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+            if distance <= max_distance {
+                let (root_a, root_b) = (find(&mut parent, i), find(&mut parent, j));
+                if root_a != root_b {
+                    parent[root_b.max(root_a)] = root_a.min(root_b);
+                }
+            }
+        }
+    }
+    
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    let mut cluster_of_root = std::collections::HashMap::new();
+    for i in 0..detections.len() {
+        let root = find(&mut parent, i);
+        let idx = *cluster_of_root.entry(root).or_insert_with(|| {
+            clusters.push(Vec::new());
+            clusters.len() - 1
+        });
+        clusters[idx].push(i);
+    }
+    clusters
+}
+
+/// 置信度最高的检测
+fn top_detection(detections: &[DetectionRecord]) -> Option<&DetectionRecord> {
+    detections.iter().max_by(|a, b| a.confidence.partial_cmp(&b.confidence).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// 输出检测的排序方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortOrder {
+    Confidence,  // 置信度从高到低（默认）
+    Position,    // 阅读顺序：先上后下，同一行（纵向相距不超过框高中位数的一半）内从左到右
+    Class,       // 按类别名，同类内置信度从高到低
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+    
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "confidence" => Ok(SortOrder::Confidence),
+            "position" => Ok(SortOrder::Position),
+            "class" => Ok(SortOrder::Class),
+            other => Err(format!("unknown sort order '{}'", other)),
+        }
+    }
+}
+
+/// 按配置的方式排序（稳定排序，相等时保持原有顺序）
+fn sort_detections(detections: &mut [DetectionRecord], order: SortOrder) {
+    let by_confidence = |a: &DetectionRecord, b: &DetectionRecord| {
+        b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal)
+    };
+    match order {
+        SortOrder::Confidence => detections.sort_by(by_confidence),
+        SortOrder::Position => sort_by_position(detections),
+        SortOrder::Class => detections.sort_by(|a, b| a.class_name.cmp(&b.class_name).then_with(|| by_confidence(a, b))),
+    }
+}
+
+/// 阅读顺序排序：先按中心点 y 排序，再把与行首纵向相距不超过容差（框高中位数的一半）的检测归为一行，
+/// 行内按 x 从左到右，避免同一行的框因 y 的微小差异而错乱
+fn sort_by_position(detections: &mut [DetectionRecord]) {
+    if detections.len() < 2 {
+        return;
+    }
+    let mut heights: Vec<f32> = detections.iter().map(|d| d.height).collect();
+    heights.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let tolerance = heights[heights.len() / 2] / 2.0;
+    
+    detections.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap_or(std::cmp::Ordering::Equal));
+    let mut row_start = 0;
+    for i in 1..=detections.len() {
+        if i == detections.len() || detections[i].y - detections[row_start].y > tolerance {
+            detections[row_start..i].sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal));
+            row_start = i;
         }
     }
+}
+
+/// 丢弃置信度低于硬性下限的检测
+fn apply_confidence_floor(detections: Vec<DetectionRecord>, floor: f32) -> Vec<DetectionRecord> {
+    detections.into_iter().filter(|d| d.confidence >= floor).collect()
+}
 
-    eprintln!("Detector node: Finished");
+/// 截断或用哨兵记录（`DetectionRecord::default()`）补齐到恰好 max_detections 条
+fn pad_to_fixed_records(detections: &[DetectionRecord], max_detections: usize) -> Vec<DetectionRecord> {
+    let mut records: Vec<DetectionRecord> = detections.iter().take(max_detections).cloned().collect();
+    records.resize_with(max_detections, DetectionRecord::default);
+    records
+}
+
+/// 解析 config 输入的 `key=value` 列表，条目以逗号、分号或换行分隔
+fn parse_config_updates(text: &str) -> Vec<(String, String)> {
+    text.split([',', ';', '\n'])
+        .filter_map(|entry| {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                return None;
+            }
+            match entry.split_once('=') {
+                Some((key, value)) => Some((key.trim().to_string(), value.trim().to_string())),
+                None => {
+                    eprintln!("Detector node: Ignoring malformed config entry '{}'", entry);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// 应用一条阈值更新，取值须在 [0, 1] 内
+fn apply_threshold_update(detector: &mut YoloDetector, conf_floor: &mut f32, key: &str, value: &str) -> Result<()> {
+    let value: f32 = value.parse().with_context(|| format!("'{}' is not a number", value))?;
+    if !(0.0..=1.0).contains(&value) {
+        anyhow::bail!("value {} out of range [0, 1]", value);
+    }
+    match key {
+        "conf_threshold" => detector.conf_threshold = value,
+        "nms_threshold" => {
+            detector.nms.threshold = value;
+            if detector.nms.adaptive {
+                eprintln!("Detector node: Adaptive NMS is enabled, nms_threshold only applies after disabling it");
+            }
+        }
+        "conf_floor" => *conf_floor = value,
+        _ => anyhow::bail!("unknown key, expected conf_threshold, nms_threshold or conf_floor"),
+    }
     Ok(())
 }
 
-fn create_mock_detections(frame_id: u32) -> Vec<Detection> {
+/// 第 processed_index 个已处理帧是否需要转发给可视化节点
+fn should_forward_visual(processed_index: u64, decimation: u64) -> bool {
+    decimation <= 1 || processed_index % decimation == 0
+}
+
+/// 第 processed_index 个已处理帧是否随检测结果一起发出；关闭透传时从不发送图像
+fn should_forward_frame(passthrough: bool, processed_index: u64, decimation: u64) -> bool {
+    passthrough && should_forward_visual(processed_index, decimation)
+}
+
+/// 尝试启用 OpenCV 的 OpenCL 支持，返回加速路径是否生效
+fn enable_opencl() -> bool {
+    match opencv::core::have_opencl() {
+        Ok(true) => {
+            if let Err(e) = opencv::core::set_use_opencl(true) {
+                eprintln!("Failed to enable OpenCL, falling back to Mat preprocessing: {}", e);
+                return false;
+            }
+            let active = opencv::core::use_opencl().unwrap_or(false);
+            eprintln!("OpenCL preprocessing active: {}", active);
+            active
+        }
+        _ => {
+            eprintln!("OpenCL not available, falling back to Mat preprocessing");
+            false
+        }
+    }
+}
+
+/// 解析形如 `1,3,640,640` 的张量形状
+fn parse_shape(spec: &str) -> Option<Vec<usize>> {
+    spec.split(',').map(|d| d.trim().parse::<usize>().ok()).collect()
+}
+
+/// 当前Unix时间（毫秒）
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 判断帧是否超出新鲜度预算，max_age_ms 为 0 时不丢弃任何帧
+fn is_frame_stale(timestamp_ms: u64, now_ms: u64, max_age_ms: u64) -> bool {
+    max_age_ms > 0 && now_ms.saturating_sub(timestamp_ms) > max_age_ms
+}
+
+fn create_mock_detections(frame_id: u32) -> Vec<DetectionRecord> {
     vec![
-        Detection {
+        DetectionRecord {
             name: format!("person_{}", frame_id % 10),
             class_name: "person".to_string(),
             confidence: 0.95,
@@ -476,8 +2610,9 @@ fn create_mock_detections(frame_id: u32) -> Vec<Detection> {
             y: 0.4,
             width: 0.2,
             height: 0.4,
+            extensions: Vec::new(),
         },
-        Detection {
+        DetectionRecord {
             name: format!("car_{}", frame_id % 5),
             class_name: "car".to_string(),
             confidence: 0.87,
@@ -485,7 +2620,861 @@ fn create_mock_detections(frame_id: u32) -> Vec<Detection> {
             y: 0.5,
             width: 0.25,
             height: 0.2,
+            extensions: Vec::new(),
         },
     ]
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn detection(class_name: &str, confidence: f32, x: f32, y: f32) -> DetectionRecord {
+        DetectionRecord {
+            name: format!("{}_0", class_name),
+            class_name: class_name.to_string(),
+            confidence,
+            x,
+            y,
+            width: 0.1,
+            height: 0.1,
+            extensions: Vec::new(),
+        }
+    }
+
+    fn classes(detections: &[DetectionRecord]) -> Vec<&str> {
+        detections.iter().map(|d| d.class_name.as_str()).collect()
+    }
+
+    /// 模型输入与原图同为 size x size、无缩放填充的后处理上下文，全局阈值 0.5
+    fn square_context<'a>(class_names: &'a [String], class_thresholds: &'a HashMap<String, f32>, size: f32) -> PostprocessContext<'a> {
+        PostprocessContext {
+            class_names,
+            conf_threshold: 0.5,
+            class_thresholds,
+            transform: ImageTransform { scale_x: 1.0, scale_y: 1.0, pad_x: 0.0, pad_y: 0.0 },
+            input_width: size,
+            input_height: size,
+            img_width: size,
+            img_height: size,
+        }
+    }
+
+    #[test]
+    fn position_sort_groups_rows_within_tolerance() {
+        // a、b、c 在同一行（y 相差小于框高的一半），d 在下一行
+        let mut detections = vec![
+            detection("c", 0.5, 0.7, 0.21),
+            detection("d", 0.5, 0.1, 0.5),
+            detection("b", 0.5, 0.4, 0.18),
+            detection("a", 0.5, 0.1, 0.22),
+        ];
+        sort_detections(&mut detections, SortOrder::Position);
+        assert_eq!(classes(&detections), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn position_sort_separates_rows_beyond_tolerance() {
+        let mut detections = vec![
+            detection("right_top", 0.5, 0.9, 0.1),
+            detection("left_low", 0.5, 0.1, 0.2),
+        ];
+        sort_detections(&mut detections, SortOrder::Position);
+        assert_eq!(classes(&detections), vec!["right_top", "left_low"]);
+    }
+
+    #[test]
+    fn confidence_and_class_sorts() {
+        let mut detections = vec![
+            detection("dog", 0.4, 0.0, 0.0),
+            detection("cat", 0.6, 0.0, 0.0),
+            detection("dog", 0.9, 0.0, 0.0),
+        ];
+        sort_detections(&mut detections, SortOrder::Confidence);
+        assert_eq!(detections.iter().map(|d| d.confidence).collect::<Vec<_>>(), vec![0.9, 0.6, 0.4]);
+        sort_detections(&mut detections, SortOrder::Class);
+        assert_eq!(classes(&detections), vec!["cat", "dog", "dog"]);
+        assert_eq!(detections[1].confidence, 0.9);
+        assert_eq!("position".parse::<SortOrder>(), Ok(SortOrder::Position));
+        assert!("random".parse::<SortOrder>().is_err());
+    }
+
+    #[test]
+    fn class_count_follows_output_layout() {
+        let v8: &[usize] = &[1, 84, 8400];
+        let v5: &[usize] = &[1, 25200, 85];
+        let v7: [&[usize]; 3] = [&[1, 3, 80, 80, 85], &[1, 3, 40, 40, 85], &[1, 3, 20, 20, 85]];
+        assert_eq!(YoloV8PostProcessor.class_count(&[v8]), Some(80));
+        assert_eq!(YoloV5PostProcessor.class_count(&[v5]), Some(80));
+        assert_eq!(YoloV7PostProcessor { anchors: Vec::new() }.class_count(&v7), Some(80));
+        assert_eq!(YoloV10PostProcessor.class_count(&[&[1, 300, 6]]), None);
+    }
+
+    #[test]
+    fn reconcile_reports_and_fixes_class_names() {
+        let names = |n: usize| (0..n).map(|i| format!("c{}", i)).collect::<Vec<_>>();
+        
+        let mut matching = names(3);
+        assert_eq!(reconcile_class_names(&mut matching, 3, true), None);
+        
+        let mut too_many = names(5);
+        let message = reconcile_class_names(&mut too_many, 3, false).unwrap();
+        assert!(message.contains("3 classes but 5 class names"), "{}", message);
+        assert_eq!(too_many.len(), 5);
+        reconcile_class_names(&mut too_many, 3, true);
+        assert_eq!(too_many, names(3));
+        
+        let mut too_few = names(2);
+        reconcile_class_names(&mut too_few, 4, true);
+        assert_eq!(too_few, vec!["c0", "c1", "class_2", "class_3"]);
+    }
+
+    #[test]
+    fn top_detection_is_one_row_or_sentinel() {
+        let detections = vec![detection("car", 0.6, 0.2, 0.2), detection("person", 0.9, 0.5, 0.5)];
+        let top = top_detection(&detections).cloned().unwrap_or_default();
+        let decoded = detection_msg::from_arrow(detection_msg::to_arrow(&[top]).as_ref()).unwrap();
+        assert_eq!(classes(&decoded), vec!["person"]);
+        
+        let none = top_detection(&[]).cloned().unwrap_or_default();
+        assert!(none.is_sentinel());
+    }
+
+    #[test]
+    fn tensor_geometry_maps_back_to_source_frame() {
+        // 1280x720 letterbox 到 640x640：缩放 0.5，上下各填充 140
+        let letterbox = TensorGeometry::Letterbox.transform(1280, 720, 640, 640);
+        assert_eq!(letterbox, ImageTransform { scale_x: 0.5, scale_y: 0.5, pad_x: 0.0, pad_y: 140.0 });
+        
+        let stretch = TensorGeometry::Stretch.transform(1280, 720, 640, 640);
+        assert_eq!((stretch.scale_x, stretch.pad_y), (0.5, 0.0));
+        assert!((stretch.scale_y - 640.0 / 720.0).abs() < 1e-6);
+        
+        let explicit = ImageTransform { scale_x: 0.25, scale_y: 0.25, pad_x: 8.0, pad_y: 0.0 };
+        assert_eq!(TensorGeometry::Explicit(explicit).transform(1280, 720, 640, 640), explicit);
+    }
+
+    #[test]
+    fn unit_interval_env_falls_back_on_invalid_values() {
+        assert_eq!(env_unit_interval("TEST_UNIT_INTERVAL_UNSET", 0.25), 0.25);
+        std::env::set_var("TEST_UNIT_INTERVAL_VALID", " 0.6 ");
+        assert_eq!(env_unit_interval("TEST_UNIT_INTERVAL_VALID", 0.25), 0.6);
+        for (key, value) in [("TEST_UNIT_INTERVAL_HIGH", "1.5"), ("TEST_UNIT_INTERVAL_NEG", "-0.1"),
+                             ("TEST_UNIT_INTERVAL_NAN", "NaN"), ("TEST_UNIT_INTERVAL_TEXT", "high")] {
+            std::env::set_var(key, value);
+            assert_eq!(env_unit_interval(key, 0.25), 0.25, "{}={} not replaced by default", key, value);
+        }
+    }
+
+    #[test]
+    fn v8_decode_reads_class_scores_without_objectness() {
+        // [1, 4 + 2, 8]：通道 4 是第一个类别分数，而不是 objectness
+        let mut output = tract_core::ndarray::Array3::<f32>::zeros((1, 6, 8));
+        for (channel, value) in [320.0, 160.0, 64.0, 32.0, 0.3, 0.9].into_iter().enumerate() {
+            output[[0, channel, 3]] = value;
+        }
+        let outputs: Vec<TValue> = vec![Tensor::from(output).into()];
+        let class_names = vec!["person".to_string(), "car".to_string()];
+        let class_thresholds = HashMap::new();
+        let ctx = square_context(&class_names, &class_thresholds, 640.0);
+
+        let processor = YoloV8PostProcessor;
+        assert!(processor.matches(&[&[1, 84, 8400]]));
+        assert!(!processor.matches(&[&[1, 8400, 85]]));
+        assert_eq!(processor.class_count(&[&[1, 84, 8400]]), Some(80));
+
+        let detections = processor.process(&outputs, &ctx);
+        assert_eq!(detections.len(), 1);
+        let detection = &detections[0];
+        assert_eq!(detection.class_name, "car");
+        assert_eq!(detection.confidence, 0.9);
+        assert_eq!((detection.x, detection.y, detection.width, detection.height), (0.5, 0.25, 0.1, 0.05));
+    }
+
+    #[test]
+    fn frame_staleness_respects_budget() {
+        assert!(!is_frame_stale(1_000, 1_200, 200));
+        assert!(is_frame_stale(1_000, 1_201, 200));
+        // 预算为 0 时不丢帧；时间戳来自未来（时钟偏差）时也不视为过期
+        assert!(!is_frame_stale(0, 1_000_000, 0));
+        assert!(!is_frame_stale(2_000, 1_000, 200));
+    }
+
+    #[test]
+    fn model_switcher_applies_hysteresis() {
+        let mut switcher = ModelSwitcher::new(100, 50, 3);
+        // 连续 3 帧超过 high_ms 才切换，中途回到滞回区间会重新计数
+        assert!(!switcher.update(120));
+        assert!(!switcher.update(120));
+        assert!(!switcher.update(80));
+        assert!(!switcher.update(120));
+        assert!(!switcher.update(120));
+        assert!(switcher.update(120));
+        // 滞回区间内保持快速模型，连续 3 帧低于 low_ms 才切回
+        assert!(switcher.update(80));
+        assert!(switcher.update(40));
+        assert!(switcher.update(40));
+        assert!(!switcher.update(40));
+    }
+
+    #[test]
+    fn pads_or_truncates_to_fixed_record_count() {
+        let detections = vec![detection("a", 0.9, 0.1, 0.1), detection("b", 0.8, 0.2, 0.2), detection("c", 0.7, 0.3, 0.3)];
+        let padded = pad_to_fixed_records(&detections, 5);
+        assert_eq!(padded.len(), 5);
+        assert_eq!(&padded[..3], &detections[..]);
+        assert!(padded[3..].iter().all(|d| *d == DetectionRecord::default()));
+
+        let truncated = pad_to_fixed_records(&detections, 2);
+        assert_eq!(classes(&truncated), vec!["a", "b"]);
+        assert!(pad_to_fixed_records(&detections, 0).is_empty());
+    }
+
+    #[test]
+    fn csv_escape_quotes_special_fields() {
+        assert_eq!(csv_escape("person"), "person");
+        assert_eq!(csv_escape("traffic light"), "traffic light");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_escape("line\nbreak"), "\"line\nbreak\"");
+    }
+
+    #[test]
+    fn confidence_floor_drops_only_lower_scores() {
+        let detections = vec![detection("low", 0.2, 0.1, 0.1), detection("edge", 0.3, 0.2, 0.2), detection("high", 0.9, 0.3, 0.3)];
+        assert_eq!(classes(&apply_confidence_floor(detections.clone(), 0.3)), vec!["edge", "high"]);
+        assert_eq!(apply_confidence_floor(detections, 0.0).len(), 3);
+    }
+
+    #[test]
+    fn adaptive_nms_threshold_interpolates_by_candidate_count() {
+        let mut config = NmsConfig {
+            threshold: 0.45,
+            adaptive: false,
+            min_threshold: 0.3,
+            max_threshold: 0.6,
+            sparse_count: 10,
+            dense_count: 110,
+        };
+        assert_eq!(config.threshold(500), 0.45);
+
+        config.adaptive = true;
+        assert_eq!(config.threshold(0), 0.6);
+        assert_eq!(config.threshold(10), 0.6);
+        assert!((config.threshold(60) - 0.45).abs() < 1e-6);
+        assert_eq!(config.threshold(110), 0.3);
+        assert_eq!(config.threshold(1000), 0.3);
+    }
+
+    #[test]
+    fn clusters_chain_nearby_detections_of_the_same_class() {
+        let detections = vec![
+            detection("person", 0.9, 0.10, 0.10),
+            detection("person", 0.9, 0.15, 0.10),
+            detection("car", 0.9, 0.12, 0.10),
+            // 与 0 号相距超过阈值，但经 1 号单链接进入同一簇
+            detection("person", 0.9, 0.20, 0.10),
+            detection("person", 0.9, 0.80, 0.80),
+        ];
+        assert_eq!(cluster_detections(&detections, 0.06), vec![vec![0, 1, 3], vec![2], vec![4]]);
+        assert!(cluster_detections(&[], 0.06).is_empty());
+    }
+
+    #[test]
+    fn timing_summary_uses_nearest_rank_percentiles() {
+        assert_eq!(TimingSummary::from_samples(&[]), None);
+        // 乱序输入，最近秩法：p50 取第 10 个，p95 取第 19 个
+        let samples: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        assert_eq!(
+            TimingSummary::from_samples(&samples),
+            Some(TimingSummary { mean_ms: 10.5, p50_ms: 10.0, p95_ms: 19.0 })
+        );
+        assert_eq!(
+            TimingSummary::from_samples(&[7.0]),
+            Some(TimingSummary { mean_ms: 7.0, p50_ms: 7.0, p95_ms: 7.0 })
+        );
+    }
+
+    #[test]
+    fn interpolation_names_map_to_opencv_flags() {
+        assert_eq!(interpolation_flag("linear"), Some(imgproc::INTER_LINEAR));
+        assert_eq!(interpolation_flag("area"), Some(imgproc::INTER_AREA));
+        assert_eq!(interpolation_flag("cubic"), Some(imgproc::INTER_CUBIC));
+        assert_eq!(interpolation_flag("nearest"), Some(imgproc::INTER_NEAREST));
+        assert_eq!(interpolation_flag("lanczos"), None);
+        assert_eq!(interpolation_flag(""), None);
+    }
+
+    #[test]
+    fn postprocessor_is_selected_by_output_shape() {
+        let processors = default_postprocessors();
+        let select = |shapes: &[&[usize]]| processors.iter().find(|p| p.matches(shapes)).map(|p| p.name());
+        assert_eq!(select(&[&[1, 84, 8400]]), Some("v8"));
+        assert_eq!(select(&[&[1, 25200, 85]]), Some("v5"));
+        assert_eq!(select(&[&[1, 300, 6]]), Some("v10"));
+        assert_eq!(select(&[&[1, 3, 80, 80, 85], &[1, 3, 40, 40, 85], &[1, 3, 20, 20, 85]]), Some("v7"));
+        assert_eq!(select(&[&[1, 4, 8400]]), None);
+        assert_eq!(select(&[&[2, 84, 8400]]), None);
+    }
+
+    #[test]
+    fn letterbox_geometry_centers_scaled_image() {
+        // 1280x720 缩放到 640x640：比例 0.5，上下各填充 140
+        assert_eq!(letterbox_geometry(1280, 720, 640, 640), (0.5, 640, 360, 0, 140));
+        // 竖屏图像左右填充
+        assert_eq!(letterbox_geometry(480, 640, 640, 640), (1.0, 480, 640, 80, 0));
+        // 奇数余量时多出的一像素落在右/下侧
+        assert_eq!(letterbox_geometry(640, 639, 640, 640), (1.0, 640, 639, 0, 0));
+    }
+
+    #[test]
+    fn class_names_fall_back_to_index_when_list_is_short() {
+        let class_names = vec!["person".to_string()];
+        let class_thresholds = HashMap::from([("person".to_string(), 0.8)]);
+        let ctx = square_context(&class_names, &class_thresholds, 640.0);
+        assert_eq!(ctx.class_name(0), "person");
+        assert_eq!(ctx.class_name(3), "class_3");
+        let detection = ctx.detection(3, 0.9, 7, (0.5, 0.5, 0.1, 0.1));
+        assert_eq!((detection.class_name.as_str(), detection.name.as_str()), ("class_3", "class_3_7"));
+        // 未命名类别使用全局阈值，已命名类别使用各自阈值
+        assert!(ctx.accepts(3, 0.6));
+        assert!(!ctx.accepts(0, 0.6));
+    }
+
+    #[test]
+    fn nms_only_suppresses_overlaps_within_a_class() {
+        let detections = vec![
+            detection("person", 0.9, 0.5, 0.5),
+            detection("person", 0.6, 0.51, 0.5),
+            detection("dog", 0.8, 0.5, 0.5),
+            detection("person", 0.7, 0.9, 0.9),
+        ];
+        let kept = non_max_suppression(detections, 0.45);
+        assert_eq!(classes(&kept), vec!["person", "dog", "person"]);
+        assert_eq!(kept.iter().map(|d| d.confidence).collect::<Vec<_>>(), vec![0.9, 0.8, 0.7]);
+    }
+
+    #[test]
+    fn v7_decodes_each_head_with_its_stride_and_anchors() {
+        // 64x64 输入的三个检测头：步长 8/16/32，每头 3 个锚框、2 个类别；logit -10 的格子会被过滤
+        let head = |grid: usize, anchor: usize, gy: usize, gx: usize, class_idx: usize| {
+            let mut values = tract_core::ndarray::Array5::<f32>::from_elem((1, 3, grid, grid, 7), -10.0);
+            // sigmoid(0) = 0.5：中心落在格子中央，宽高恰为锚框尺寸
+            for c in 0..4 {
+                values[[0, anchor, gy, gx, c]] = 0.0;
+            }
+            values[[0, anchor, gy, gx, 4]] = 6.0;
+            values[[0, anchor, gy, gx, 5 + class_idx]] = 6.0;
+            TValue::from(Tensor::from(values))
+        };
+        // 输出顺序打乱，解码时按网格大小对应 P3/P4/P5 锚框
+        let outputs = vec![head(2, 2, 1, 1, 0), head(8, 1, 2, 3, 0), head(4, 0, 1, 2, 1)];
+        let class_names = vec!["person".to_string(), "car".to_string()];
+        let class_thresholds = HashMap::new();
+        let ctx = square_context(&class_names, &class_thresholds, 64.0);
+        let processor = YoloV7PostProcessor { anchors: YOLOV7_ANCHORS.iter().map(|head| head.to_vec()).collect() };
+        assert!(processor.matches(&outputs.iter().map(|o| o.shape()).collect::<Vec<_>>()));
+
+        let detections = processor.process(&outputs, &ctx);
+        let pixel_boxes: Vec<(&str, [f32; 4])> = detections.iter()
+            .map(|d| (d.class_name.as_str(), [d.x * 64.0, d.y * 64.0, d.width * 64.0, d.height * 64.0]))
+            .collect();
+        assert_eq!(pixel_boxes, vec![
+            // 步长 8，格子 (3, 2)，锚框 (19, 36)
+            ("person", [28.0, 20.0, 19.0, 36.0]),
+            // 步长 16，格子 (2, 1)，锚框 (36, 75)
+            ("car", [40.0, 24.0, 36.0, 75.0]),
+            // 步长 32，格子 (1, 1)，锚框 (459, 401)
+            ("person", [48.0, 48.0, 459.0, 401.0]),
+        ]);
+        assert!(detections.iter().all(|d| d.confidence > 0.99));
+        assert_eq!(non_max_suppression(detections, 0.45).len(), 3);
+    }
+
+    #[test]
+    fn v5_decode_multiplies_objectness_into_class_score() {
+        // [1, 4, 5 + 2]：每行 cx, cy, w, h, objectness, 两个类别分数
+        let rows: [[f32; 7]; 4] = [
+            [320.0, 160.0, 64.0, 32.0, 0.8, 0.25, 0.9],
+            // 类别分数 0.55 单独可过阈值，乘以 objectness 后为 0.495，被过滤
+            [100.0, 100.0, 10.0, 10.0, 0.9, 0.55, 0.1],
+            // objectness 低于阈值，直接跳过
+            [200.0, 200.0, 10.0, 10.0, 0.3, 0.0, 1.0],
+            [0.0; 7],
+        ];
+        let output = tract_core::ndarray::Array3::from_shape_fn((1, 4, 7), |(_, i, c)| rows[i][c]);
+        let outputs: Vec<TValue> = vec![Tensor::from(output).into()];
+        let class_names = vec!["person".to_string(), "car".to_string()];
+        let class_thresholds = HashMap::new();
+        let ctx = square_context(&class_names, &class_thresholds, 640.0);
+
+        let detections = YoloV5PostProcessor.process(&outputs, &ctx);
+        assert_eq!(detections.len(), 1);
+        let detection = &detections[0];
+        assert_eq!(detection.class_name, "car");
+        assert_eq!(detection.confidence, 0.8 * 0.9);
+        assert_eq!((detection.x, detection.y, detection.width, detection.height), (0.5, 0.25, 0.1, 0.05));
+    }
+
+    #[test]
+    fn v10_decode_converts_corners_without_nms() {
+        // [1, 3, 6]：每行 x1, y1, x2, y2, score, class_id
+        let rows: [[f32; 6]; 3] = [
+            [100.0, 200.0, 300.0, 400.0, 0.9, 1.0],
+            [100.0, 200.0, 300.0, 400.0, 0.3, 1.0],
+            // 类别名列表之外的类别以 class_{idx} 命名
+            [0.0, 0.0, 64.0, 64.0, 0.7, 5.0],
+        ];
+        let output = tract_core::ndarray::Array3::from_shape_fn((1, 3, 6), |(_, i, c)| rows[i][c]);
+        let outputs: Vec<TValue> = vec![Tensor::from(output).into()];
+        let class_names = vec!["person".to_string(), "car".to_string()];
+        let class_thresholds = HashMap::new();
+        let ctx = square_context(&class_names, &class_thresholds, 640.0);
+
+        let processor = YoloV10PostProcessor;
+        assert!(!processor.needs_nms());
+        let detections = processor.process(&outputs, &ctx);
+        assert_eq!(classes(&detections), vec!["car", "class_5"]);
+        let first = &detections[0];
+        assert_eq!(first.confidence, 0.9);
+        assert_eq!((first.x, first.y, first.width, first.height), (0.3125, 0.46875, 0.3125, 0.3125));
+        let second = &detections[1];
+        assert_eq!((second.x, second.y, second.width, second.height), (0.05, 0.05, 0.1, 0.1));
+    }
+
+    #[test]
+    fn tensor_input_rejects_mismatched_shape_and_dtype() {
+        let expected = [1, 3, 4, 4];
+        let values = vec![0.5; 48];
+        let tensor = tensor_input(&values, None, &expected).unwrap();
+        assert_eq!(tensor.shape(), &expected);
+        assert!(tensor_input(&values, Some(&[1, 3, 4, 4]), &expected).is_ok());
+
+        // 形状与模型输入不同（即使元素数相同）、或数据长度不符都返回错误
+        assert!(tensor_input(&values, Some(&[1, 3, 2, 8]), &expected).is_err());
+        assert!(tensor_input(&values[..47], None, &expected).is_err());
+        assert!(tensor_input(&vec![0.5; 3 * 8 * 8], Some(&[1, 3, 8, 8]), &expected).is_err());
+
+        assert_eq!(tensor_values(&Float32Array::from(vec![0.25, 0.5])).unwrap(), vec![0.25, 0.5]);
+        assert!(tensor_values(&UInt8Array::from(vec![1u8, 2, 3])).is_err());
+    }
+
+    /// 没有模型文件时检测器以模拟模式创建，足以测试阈值等运行时状态
+    fn mock_detector() -> YoloDetector {
+        YoloDetector::new("/nonexistent/model.onnx").unwrap()
+    }
+
+    #[test]
+    fn parses_config_update_entries() {
+        let updates = parse_config_updates("conf_threshold=0.4; nms_threshold = 0.5\nmalformed,,conf_floor=0.2");
+        assert_eq!(updates, vec![
+            ("conf_threshold".to_string(), "0.4".to_string()),
+            ("nms_threshold".to_string(), "0.5".to_string()),
+            ("conf_floor".to_string(), "0.2".to_string()),
+        ]);
+
+        let mut detector = mock_detector();
+        let mut conf_floor = 0.0;
+        for (key, value) in &updates {
+            apply_threshold_update(&mut detector, &mut conf_floor, key, value).unwrap();
+        }
+        assert_eq!((detector.conf_threshold, detector.nms.threshold, conf_floor), (0.4, 0.5, 0.2));
+    }
+
+    #[test]
+    fn rejects_malformed_or_out_of_range_updates() {
+        let mut detector = mock_detector();
+        let mut conf_floor = 0.0;
+        let before = detector.conf_threshold;
+        for (key, value) in [("conf_threshold", "1.5"), ("conf_threshold", "-0.1"), ("conf_threshold", "high"), ("iou", "0.5")] {
+            assert!(apply_threshold_update(&mut detector, &mut conf_floor, key, value).is_err(), "{}={} accepted", key, value);
+        }
+        assert_eq!((detector.conf_threshold, conf_floor), (before, 0.0));
+    }
+
+    #[test]
+    fn raised_threshold_drops_previously_kept_detection() {
+        let mut detector = mock_detector();
+        let mut conf_floor = 0.0;
+        apply_threshold_update(&mut detector, &mut conf_floor, "conf_threshold", "0.2").unwrap();
+        let class_names = vec!["person".to_string()];
+        let class_thresholds = HashMap::new();
+        let candidate = detection("person", 0.3, 0.5, 0.5);
+        let ctx = PostprocessContext { conf_threshold: detector.conf_threshold, ..square_context(&class_names, &class_thresholds, 640.0) };
+        assert!(ctx.accepts(0, candidate.confidence));
+        assert_eq!(apply_confidence_floor(vec![candidate.clone()], conf_floor).len(), 1);
+
+        apply_threshold_update(&mut detector, &mut conf_floor, "conf_threshold", "0.5").unwrap();
+        apply_threshold_update(&mut detector, &mut conf_floor, "conf_floor", "0.5").unwrap();
+        let ctx = PostprocessContext { conf_threshold: detector.conf_threshold, ..square_context(&class_names, &class_thresholds, 640.0) };
+        assert!(!ctx.accepts(0, candidate.confidence));
+        assert!(apply_confidence_floor(vec![candidate], conf_floor).is_empty());
+    }
+
+    #[test]
+    fn preprocess_pipeline_parses_steps_and_presets() {
+        use PreprocessStep::*;
+        let rgb = ColorConvert(imgproc::COLOR_BGR2RGB);
+        let scale = Normalize(1.0 / 255.0);
+        assert_eq!(PreprocessPipeline::parse(" resize, bgr2rgb ,normalize,chw").unwrap().steps, vec![Resize, rgb, scale, ToChw]);
+        assert_eq!(PreprocessPipeline::parse("letterbox,chw").unwrap().steps, vec![Letterbox, ToChw]);
+
+        assert_eq!(PreprocessPipeline::preset("letterbox").unwrap().steps, vec![Letterbox, rgb, scale, ToChw]);
+        assert_eq!(PreprocessPipeline::preset("stretch").unwrap().steps, vec![rgb, Resize, scale, ToChw]);
+        assert_eq!(PreprocessPipeline::parse("stretch").unwrap().steps, PreprocessPipeline::preset("stretch").unwrap().steps);
+        assert!(PreprocessPipeline::preset("resize").is_none());
+        assert_eq!(PreprocessPipeline::preset("letterbox").unwrap().interpolation, imgproc::INTER_LINEAR);
+
+        // 未知步骤、缺少或重复的几何步骤、chw 不在最后都被拒绝
+        for spec in ["resize,blur,chw", "bgr2rgb,chw", "resize,letterbox,chw", "resize,chw,normalize", "resize", ""] {
+            assert!(PreprocessPipeline::parse(spec).is_err(), "'{}' accepted", spec);
+        }
+    }
+
+    /// 预处理输入张量所在的共享缓冲区地址
+    fn input_buffer_ptr(value: &TValue) -> *const Tensor {
+        match value {
+            TValue::Const(tensor) => std::sync::Arc::as_ptr(tensor),
+            _ => panic!("preprocess should return the shared input buffer"),
+        }
+    }
+
+    #[test]
+    fn preprocess_writes_rgb_chw_scaled_tensor_into_reused_buffer() {
+        let detector = mock_detector();
+        // 2x2 BGR 图像，模型输入同为 2x2：letterbox 不缩放也不填充
+        let image: Vec<u8> = vec![10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120];
+        let (first, transform) = detector.preprocess(&image, 2, 2, 2, 2).unwrap();
+        assert_eq!(transform, ImageTransform { scale_x: 1.0, scale_y: 1.0, pad_x: 0.0, pad_y: 0.0 });
+        assert_eq!(first.shape(), &[1, 3, 2, 2]);
+        // 与旧的逐像素实现一致：R、G、B 三个平面依次排列，每个值除以 255
+        let expected = [30, 60, 90, 120, 20, 50, 80, 110, 10, 40, 70, 100].map(|v| v as f32 / 255.0);
+        let values = first.as_slice::<f32>().unwrap().to_vec();
+        assert!(values.iter().zip(expected).all(|(v, e)| (v - e).abs() < 1e-6), "{:?}", values);
+
+        // 上一帧的张量仍被持有时另行分配，不覆盖其内容
+        let (second, _) = detector.preprocess(&[255; 12], 2, 2, 2, 2).unwrap();
+        assert_ne!(input_buffer_ptr(&first), input_buffer_ptr(&second));
+        assert_eq!(first.as_slice::<f32>().unwrap(), &values[..]);
+
+        // 释放后下一帧原地复用同一缓冲区，形状保持不变
+        let second_ptr = input_buffer_ptr(&second);
+        drop(first);
+        drop(second);
+        let (third, _) = detector.preprocess(&image, 2, 2, 2, 2).unwrap();
+        assert_eq!(input_buffer_ptr(&third), second_ptr);
+        assert_eq!(third.shape(), &[1, 3, 2, 2]);
+        drop(third);
+
+        // 输入尺寸改变（切换模型）时按新形状重新分配
+        let (resized, _) = detector.preprocess(&image, 2, 2, 4, 4).unwrap();
+        assert_eq!(resized.shape(), &[1, 3, 4, 4]);
+    }
+
+    /// 每个测试独立的临时目录
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("detector_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 目录中除 detections.csv 外的轮转文件内容
+    fn rotated_files(dir: &Path) -> Vec<String> {
+        std::fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().is_some_and(|name| name != "detections.csv"))
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn csv_header_is_written_once_per_file() {
+        let dir = scratch_dir("csv_header");
+        let path = dir.join("detections.csv");
+        let frame = [detection("person", 0.9, 0.5, 0.5)];
+        {
+            let mut exporter = CsvExporter::open(path.to_str().unwrap(), 0, Duration::ZERO).unwrap();
+            exporter.write_frame(1, 1000, &frame).unwrap();
+            exporter.write_frame(2, 1033, &frame).unwrap();
+        }
+        // 重新打开已有文件时继续追加，不重复写表头
+        let mut exporter = CsvExporter::open(path.to_str().unwrap(), 0, Duration::ZERO).unwrap();
+        exporter.write_frame(3, 1066, &frame).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().filter(|line| *line == CSV_HEADER).count(), 1);
+        assert_eq!(text.lines().next(), Some(CSV_HEADER));
+        assert_eq!(text.lines().count(), 4);
+        assert!(rotated_files(&dir).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_rotates_by_size() {
+        let dir = scratch_dir("csv_size");
+        let path = dir.join("detections.csv");
+        let frame = [detection("person", 0.9, 0.5, 0.5)];
+        // 表头加一行即超过 100 字节，下一帧写入前轮转
+        let mut exporter = CsvExporter::open(path.to_str().unwrap(), 100, Duration::ZERO).unwrap();
+        exporter.write_frame(1, 1000, &frame).unwrap();
+        assert!(rotated_files(&dir).is_empty());
+        exporter.write_frame(2, 1033, &frame).unwrap();
+
+        let rotated = rotated_files(&dir);
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(rotated[0].lines().collect::<Vec<_>>(), vec![CSV_HEADER, format_csv_rows(1, 1000, &frame)[0].as_str()]);
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(current.lines().collect::<Vec<_>>(), vec![CSV_HEADER, format_csv_rows(2, 1033, &frame)[0].as_str()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_rotates_by_time() {
+        let dir = scratch_dir("csv_time");
+        let path = dir.join("detections.csv");
+        let frame = [detection("person", 0.9, 0.5, 0.5)];
+        let mut exporter = CsvExporter::open(path.to_str().unwrap(), 0, Duration::from_millis(20)).unwrap();
+        exporter.write_frame(1, 1000, &frame).unwrap();
+        exporter.write_frame(2, 1005, &frame).unwrap();
+        assert!(rotated_files(&dir).is_empty());
+
+        std::thread::sleep(Duration::from_millis(30));
+        exporter.write_frame(3, 1040, &frame).unwrap();
+        let rotated = rotated_files(&dir);
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(rotated[0].lines().count(), 3);
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(current.lines().collect::<Vec<_>>(), vec![CSV_HEADER, format_csv_rows(3, 1040, &frame)[0].as_str()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detection_log_line_summarizes_frame() {
+        let detections = [detection("person", 0.954, 0.1, 0.1), detection("traffic light", 0.5, 0.2, 0.2)];
+        assert_eq!(format_detection_log_line(12, &detections), "frame_id=12 count=2 person:0.95 traffic_light:0.50");
+        assert_eq!(format_detection_log_line(3, &[]), "frame_id=3 count=0");
+    }
+
+    #[test]
+    fn rate_limit_allows_one_event_per_window() {
+        let mut limit = RateLimit::new(Duration::from_millis(1000));
+        let start = std::time::Instant::now();
+        assert!(limit.allow(start));
+        assert!(!limit.allow(start + Duration::from_millis(999)));
+        // 窗口从上一次放行开始计算，被拒绝的事件不重置窗口
+        assert!(limit.allow(start + Duration::from_millis(1000)));
+        assert!(!limit.allow(start + Duration::from_millis(1500)));
+        assert!(limit.allow(start + Duration::from_millis(2000)));
+
+        let mut unlimited = RateLimit::new(Duration::ZERO);
+        assert!(unlimited.allow(start));
+        assert!(unlimited.allow(start));
+    }
+
+    #[test]
+    fn heartbeat_fires_only_after_idle_period_and_resets_on_frames() {
+        let mut heartbeat = IdleTimer::new(Duration::from_millis(500));
+        let start = std::time::Instant::now();
+        heartbeat.mark(start);
+        assert!(!heartbeat.is_due(start + Duration::from_millis(499)));
+        assert!(heartbeat.is_due(start + Duration::from_millis(500)));
+
+        // 收到帧后重新计时
+        heartbeat.mark(start + Duration::from_millis(400));
+        assert!(!heartbeat.is_due(start + Duration::from_millis(800)));
+        assert!(heartbeat.is_due(start + Duration::from_millis(900)));
+        // 发送心跳后同样重新计时，停滞期间按间隔重复触发
+        heartbeat.mark(start + Duration::from_millis(900));
+        assert!(!heartbeat.is_due(start + Duration::from_millis(1300)));
+        assert!(heartbeat.is_due(start + Duration::from_millis(1400)));
+
+        // 事件等待不超过心跳间隔；间隔为 0 时关闭
+        assert_eq!(heartbeat.recv_timeout(Duration::from_secs(1)), Duration::from_millis(500));
+        let disabled = IdleTimer::new(Duration::ZERO);
+        assert!(!disabled.is_due(start + Duration::from_secs(3600)));
+        assert_eq!(disabled.recv_timeout(Duration::from_secs(1)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn keepalive_runs_only_while_idle() {
+        let mut keepalive = IdleTimer::new(Duration::from_millis(5000));
+        let start = std::time::Instant::now();
+        keepalive.mark(start);
+        // 帧持续到达（每帧都 mark）时始终不触发保温推理
+        for frame in 1..=20 {
+            let now = start + Duration::from_millis(frame * 1000);
+            assert!(!keepalive.is_due(now), "keep-alive due while frames arrive at {:?}", now - start);
+            keepalive.mark(now);
+        }
+        // 输入停滞超过空闲时间后触发，保温后再等待一个空闲周期
+        let last_frame = start + Duration::from_millis(20_000);
+        assert!(!keepalive.is_due(last_frame + Duration::from_millis(4999)));
+        let idle = last_frame + Duration::from_millis(5000);
+        assert!(keepalive.is_due(idle));
+        keepalive.mark(idle);
+        assert!(!keepalive.is_due(idle + Duration::from_millis(1000)));
+        assert!(keepalive.is_due(idle + Duration::from_millis(5000)));
+    }
+
+    #[test]
+    fn text_prompts_build_embedding_tensor_and_check_model_input() {
+        let prompts = vec!["person".to_string(), "red car".to_string(), "dog".to_string()];
+        let embeddings: Vec<f32> = (0..12).map(|v| v as f32).collect();
+        let text = TextPrompts::new(prompts.clone(), embeddings).unwrap();
+        assert_eq!(text.dim, 4);
+        let tensor = text.to_tensor().unwrap();
+        assert_eq!(tensor.shape(), &[1, 3, 4]);
+        // 按行存储：第二个提示词的嵌入为 4..8
+        assert_eq!(&tensor.as_slice::<f32>().unwrap()[4..8], &[4.0, 5.0, 6.0, 7.0]);
+
+        // 嵌入数量无法按提示词均分
+        assert!(TextPrompts::new(prompts, vec![0.0; 10]).is_err());
+        assert!(TextPrompts::new(Vec::new(), vec![0.0; 4]).is_err());
+
+        // 模型文本输入声明的形状须与提示词一致，动态形状不做限制
+        assert!(check_text_input_shape(Some(&[1, 3, 4]), 3, 4).is_ok());
+        assert!(check_text_input_shape(None, 3, 4).is_ok());
+        assert!(check_text_input_shape(Some(&[1, 3, 512]), 3, 4).is_err());
+        assert!(check_text_input_shape(Some(&[1, 80, 4]), 3, 4).is_err());
+    }
+
+    #[test]
+    fn cloned_snapshot_handle_sees_published_frame() {
+        let handle = SnapshotHandle::default();
+        let reader = handle.clone();
+        assert!(reader.latest().is_none());
+
+        let publisher = std::thread::spawn(move || {
+            handle.publish(DetectionSnapshot {
+                frame_id: 7,
+                timestamp_ms: 1_000,
+                frame_width: 1280,
+                frame_height: 720,
+                detections: vec![detection("person", 0.9, 0.5, 0.5)],
+            });
+            handle
+        });
+        let handle = publisher.join().unwrap();
+        let latest = reader.latest().unwrap();
+        assert_eq!((latest.frame_id, latest.frame_width, latest.frame_height), (7, 1280, 720));
+        assert_eq!(classes(&latest.detections), vec!["person"]);
+
+        // 新的一帧替换旧快照
+        handle.publish(DetectionSnapshot { frame_id: 8, timestamp_ms: 1_033, frame_width: 1280, frame_height: 720, detections: Vec::new() });
+        let latest = reader.latest().unwrap();
+        assert_eq!(latest.frame_id, 8);
+        assert!(latest.detections.is_empty());
+    }
+
+    #[test]
+    fn visual_decimation_forwards_every_nth_processed_frame() {
+        // 检测结果每个处理帧都发送，frame 只在抽帧命中时转发，两者互不影响
+        let (mut detection_sends, mut forwarded) = (0, Vec::new());
+        for processed_index in 0..10 {
+            detection_sends += 1;
+            if should_forward_visual(processed_index, 3) {
+                forwarded.push(processed_index);
+            }
+        }
+        assert_eq!(detection_sends, 10);
+        assert_eq!(forwarded, vec![0, 3, 6, 9]);
+        // 0 和 1 都表示不抽帧
+        assert!((0..10).all(|i| should_forward_visual(i, 0)));
+        assert!((0..10).all(|i| should_forward_visual(i, 1)));
+    }
+
+    #[test]
+    fn source_dimensions_prefer_upstream_source_size() {
+        let mut parameters = MetadataParameters::new();
+        assert_eq!(source_dimensions(&parameters, 640, 360), (640, 360));
+
+        parameters.insert("source_width".to_string(), dora_node_api::Parameter::String("1920".to_string()));
+        parameters.insert("source_height".to_string(), dora_node_api::Parameter::Integer(1080));
+        assert_eq!(source_dimensions(&parameters, 640, 360), (1920, 1080));
+    }
+
+    #[test]
+    fn boxes_stay_aligned_when_capture_and_display_sizes_differ() {
+        // 相机 1920x1080 采集，缩小为 640x360 送检，模型输入 640x640 letterbox（上下各填充 140）
+        let mut parameters = MetadataParameters::new();
+        parameters.insert("source_width".to_string(), dora_node_api::Parameter::String("1920".to_string()));
+        parameters.insert("source_height".to_string(), dora_node_api::Parameter::String("1080".to_string()));
+        let (frame_w, frame_h) = (640, 360);
+        let (source_w, source_h) = source_dimensions(&parameters, frame_w, frame_h);
+
+        let names: Vec<String> = Vec::new();
+        let thresholds = HashMap::new();
+        let mut ctx = square_context(&names, &thresholds, 640.0);
+        ctx.transform = TensorGeometry::Letterbox.transform(frame_w, frame_h, 640, 640);
+        ctx.img_width = frame_w as f32;
+        ctx.img_height = frame_h as f32;
+
+        // 送检帧中心 (320, 90)、尺寸 64x36 的框，在模型输入中位于 (320, 230)
+        let (x, y, w, h) = ctx.to_normalized(320.0, 230.0, 64.0, 36.0);
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+        // 在送检帧上还原
+        assert!(close(x * frame_w as f32, 320.0) && close(y * frame_h as f32, 90.0));
+        assert!(close(w * frame_w as f32, 64.0) && close(h * frame_h as f32, 36.0));
+        // 按元数据中的源尺寸在原始分辨率画面上还原，应为同一位置放大 3 倍
+        assert!(close(x * source_w as f32, 960.0) && close(y * source_h as f32, 270.0));
+        assert!(close(w * source_w as f32, 192.0) && close(h * source_h as f32, 108.0));
+    }
+
+    #[test]
+    fn disabled_passthrough_never_forwards_frames() {
+        assert!((0..10).all(|i| !should_forward_frame(false, i, 1)));
+        assert!((0..10).all(|i| !should_forward_frame(false, i, 3)));
+        assert!((0..10).all(|i| should_forward_frame(true, i, 1)));
+        assert_eq!((0..9).filter(|&i| should_forward_frame(true, i, 3)).count(), 3);
+    }
+
+    #[test]
+    fn mock_detect_image_lines_parse_as_text_format() {
+        let dir = scratch_dir("detect_image");
+        let path = dir.join("fixture.png").to_string_lossy().into_owned();
+        let fixture = Mat::new_rows_cols_with_default(8, 8, opencv::core::CV_8UC3, opencv::core::Scalar::all(114.0)).unwrap();
+        opencv::imgcodecs::imwrite(&path, &fixture, &opencv::core::Vector::new()).unwrap();
+
+        let lines = detect_image_lines(&path, "/nonexistent/model.onnx", true).unwrap();
+        let parsed = detection_msg::from_text_lines(&lines.join("\n")).unwrap();
+        let expected = create_mock_detections(0);
+        assert_eq!(parsed.len(), expected.len());
+        for (p, e) in parsed.iter().zip(&expected) {
+            assert_eq!(p.class_name, e.class_name);
+            assert!((p.confidence - e.confidence).abs() < 1e-4);
+            assert!((p.x - e.x).abs() < 1e-4 && (p.y - e.y).abs() < 1e-4);
+            assert!((p.width - e.width).abs() < 1e-4 && (p.height - e.height).abs() < 1e-4);
+        }
+
+        // 未加 --mock 时缺少模型应报错
+        assert!(detect_image_lines(&path, "/nonexistent/model.onnx", false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opencl_preprocessing_matches_mat_path() {
+        if !enable_opencl() {
+            eprintln!("OpenCL unavailable, skipping UMat/Mat preprocessing comparison");
+            return;
+        }
+        let mut cpu = mock_detector();
+        cpu.use_opencl = false;
+        let mut gpu = mock_detector();
+        gpu.use_opencl = true;
+
+        // 40x30 的渐变图，letterbox 到 32x32 时既缩放又填充
+        let image: Vec<u8> = (0..30u32).flat_map(|y| (0..40u32).flat_map(move |x| [x * 6, y * 8, x * 3 + y * 4])).map(|v| v as u8).collect();
+        let (expected, expected_transform) = cpu.preprocess(&image, 40, 30, 32, 32).unwrap();
+        let (actual, actual_transform) = gpu.preprocess(&image, 40, 30, 32, 32).unwrap();
+        assert_eq!(actual_transform, expected_transform);
+        assert_eq!(actual.shape(), expected.shape());
+        // OpenCL 内核的插值舍入可能与 CPU 相差一个灰度级
+        let max_diff = actual.as_slice::<f32>().unwrap().iter()
+            .zip(expected.as_slice::<f32>().unwrap())
+            .map(|(a, e)| (a - e).abs())
+            .fold(0.0f32, f32::max);
+        assert!(max_diff <= 1.5 / 255.0, "max difference {}", max_diff);
+    }
+}
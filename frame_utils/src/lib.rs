@@ -0,0 +1,42 @@
+//! 各节点共用的 OpenCV 图像帧工具（依赖 OpenCV，与无外部依赖的 node_utils 分开）
+
+use anyhow::{Context, Result};
+use opencv::core::Mat;
+use opencv::prelude::*;
+
+/// 将图像字节复制进已分配的 Mat，长度与 Mat 字节容量不一致时返回错误而不是复制
+pub fn copy_into_mat(mat: &mut Mat, data: &[u8]) -> Result<()> {
+    let capacity = mat.total() * mat.elem_size().context("Failed to query Mat element size")?;
+    if data.len() != capacity {
+        anyhow::bail!(
+            "Image buffer size {} does not match Mat capacity {} ({}x{}x{})",
+            data.len(), capacity, mat.cols(), mat.rows(), mat.channels()
+        );
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), mat.data_mut(), data.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::core::CV_8UC3;
+
+    #[test]
+    fn copies_matching_buffer() {
+        let mut mat = Mat::new_rows_cols_with_default(2, 3, CV_8UC3, 0.into()).unwrap();
+        let data: Vec<u8> = (0..18).collect();
+        copy_into_mat(&mut mat, &data).unwrap();
+        assert_eq!(mat.data_bytes().unwrap(), data.as_slice());
+    }
+
+    #[test]
+    fn rejects_mismatched_buffer() {
+        let mut mat = Mat::new_rows_cols_with_default(2, 3, CV_8UC3, 0.into()).unwrap();
+        assert!(copy_into_mat(&mut mat, &[0u8; 17]).is_err());
+        assert!(copy_into_mat(&mut mat, &[0u8; 19]).is_err());
+        assert!(mat.data_bytes().unwrap().iter().all(|&b| b == 0));
+    }
+}
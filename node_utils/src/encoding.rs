@@ -0,0 +1,64 @@
+//! 图像帧的编码方式，由 frame 输出元数据中的 `encoding` 参数指明
+
+/// 元数据参数名
+pub const ENCODING_PARAM: &str = "encoding";
+
+/// 帧编码：原始 BGR 像素（默认，未设置 `encoding` 参数时亦按此处理）或 JPEG
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameEncoding {
+    #[default]
+    Raw,
+    Jpeg,
+}
+
+impl FrameEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FrameEncoding::Raw => "raw",
+            FrameEncoding::Jpeg => "jpeg",
+        }
+    }
+}
+
+impl std::str::FromStr for FrameEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "raw" => Ok(FrameEncoding::Raw),
+            "jpeg" | "jpg" => Ok(FrameEncoding::Jpeg),
+            other => Err(format!("unknown frame encoding '{}', expected raw or jpeg", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_encoding_names() {
+        assert_eq!("raw".parse::<FrameEncoding>(), Ok(FrameEncoding::Raw));
+        assert_eq!(" JPEG ".parse::<FrameEncoding>(), Ok(FrameEncoding::Jpeg));
+        assert_eq!("jpg".parse::<FrameEncoding>(), Ok(FrameEncoding::Jpeg));
+        assert!("png".parse::<FrameEncoding>().is_err());
+        assert_eq!(FrameEncoding::default(), FrameEncoding::Raw);
+    }
+
+    #[test]
+    fn encoding_param_round_trips() {
+        for encoding in [FrameEncoding::Raw, FrameEncoding::Jpeg] {
+            let mut parameters = HashMap::new();
+            parameters.insert(ENCODING_PARAM.to_string(), encoding.as_str().to_string());
+            let parsed = parameters[ENCODING_PARAM].parse::<FrameEncoding>();
+            assert_eq!(parsed, Ok(encoding));
+        }
+        // 未设置 encoding 参数时按原始像素处理
+        let parameters: HashMap<String, String> = HashMap::new();
+        let parsed = parameters
+            .get(ENCODING_PARAM)
+            .map_or(Ok(FrameEncoding::default()), |s| s.parse::<FrameEncoding>());
+        assert_eq!(parsed, Ok(FrameEncoding::Raw));
+    }
+}
@@ -0,0 +1,90 @@
+//! 按 frame_id 序列检测上游丢帧
+
+/// 跟踪输入帧的 frame_id 序列，检测不连续（丢帧）并累计丢帧数；
+/// frame_id 按 u32 回绕处理，向后跳变（乱序或上游重启）只重新同步不计为丢帧
+#[derive(Debug, Clone, Default)]
+pub struct FrameGapTracker {
+    last_id: Option<u32>,
+    dropped_frames: u64,
+}
+
+impl FrameGapTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一个 frame_id，若与上一帧之间有缺口则返回缺失的帧数
+    pub fn observe(&mut self, frame_id: u32) -> Option<u32> {
+        let gap = match self.last_id {
+            Some(last) => {
+                let delta = frame_id.wrapping_sub(last);
+                if delta > 1 && delta < u32::MAX / 2 {
+                    Some(delta - 1)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+        if let Some(missing) = gap {
+            self.dropped_frames += missing as u64;
+        }
+        self.last_id = Some(frame_id);
+        gap
+    }
+
+    /// 累计缺失的帧数
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_ids_have_no_gap() {
+        let mut tracker = FrameGapTracker::new();
+        for id in 0..5 {
+            assert_eq!(tracker.observe(id), None);
+        }
+        assert_eq!(tracker.dropped_frames(), 0);
+    }
+
+    #[test]
+    fn gaps_are_counted() {
+        let mut tracker = FrameGapTracker::new();
+        tracker.observe(10);
+        assert_eq!(tracker.observe(13), Some(2));
+        assert_eq!(tracker.observe(14), None);
+        assert_eq!(tracker.observe(20), Some(5));
+        assert_eq!(tracker.dropped_frames(), 7);
+    }
+
+    #[test]
+    fn wraparound_is_continuous() {
+        let mut tracker = FrameGapTracker::new();
+        tracker.observe(u32::MAX - 1);
+        assert_eq!(tracker.observe(u32::MAX), None);
+        assert_eq!(tracker.observe(0), None);
+        assert_eq!(tracker.observe(2), Some(1));
+        assert_eq!(tracker.dropped_frames(), 1);
+    }
+
+    #[test]
+    fn gap_across_wraparound_is_counted() {
+        let mut tracker = FrameGapTracker::new();
+        tracker.observe(u32::MAX - 1);
+        assert_eq!(tracker.observe(1), Some(2));
+    }
+
+    #[test]
+    fn backward_jump_resyncs_without_counting() {
+        let mut tracker = FrameGapTracker::new();
+        tracker.observe(100);
+        assert_eq!(tracker.observe(5), None);
+        assert_eq!(tracker.observe(6), None);
+        assert_eq!(tracker.dropped_frames(), 0);
+    }
+}
@@ -0,0 +1,116 @@
+//! 类别分组：把细粒度类别归并为操作人员关心的粗粒度分组（如 car/truck/bus → vehicle）
+
+/// 一组类别分组定义，格式为 `vehicle=car+truck+bus;animal=cat+dog`。
+/// 各分组相互独立，同一类别可以同时属于多个分组
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClassGroups {
+    groups: Vec<(String, Vec<String>)>,
+}
+
+impl ClassGroups {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut groups = Vec::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, members) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("group '{}' is missing '='", entry))?;
+            let name = name.trim();
+            let members: Vec<String> = members
+                .split('+')
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(str::to_string)
+                .collect();
+            if name.is_empty() || members.is_empty() {
+                return Err(format!("group '{}' needs a name and at least one class", entry));
+            }
+            groups.push((name.to_string(), members));
+        }
+        Ok(Self { groups })
+    }
+
+    /// 从环境变量读取，未设置时为空，格式错误时打印警告并返回空分组
+    pub fn from_env(key: &str) -> Self {
+        match std::env::var(key) {
+            Ok(spec) => Self::parse(&spec).unwrap_or_else(|e| {
+                eprintln!("Invalid {} '{}': {}, class grouping disabled", key, spec, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// 类别所属的全部分组名，按定义顺序
+    pub fn groups_of<'a>(&'a self, class_name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.groups
+            .iter()
+            .filter(move |(_, members)| members.iter().any(|m| m == class_name))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// 用于显示的分组名：取第一个包含该类别的分组
+    pub fn display_name<'a>(&'a self, class_name: &'a str) -> Option<&'a str> {
+        self.groups_of(class_name).next()
+    }
+
+    /// 统计每个分组的检测数量，按定义顺序返回（数量为 0 的分组也包含在内）
+    pub fn count<'a, I>(&self, class_names: I) -> Vec<(&str, usize)>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut counts: Vec<(&str, usize)> = self.groups.iter().map(|(name, _)| (name.as_str(), 0)).collect();
+        for class_name in class_names {
+            for (idx, (_, members)) in self.groups.iter().enumerate() {
+                if members.iter().any(|m| m == class_name) {
+                    counts[idx].1 += 1;
+                }
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_group_definitions() {
+        let groups = ClassGroups::parse(" vehicle = car + truck+bus ;animal=cat+dog;").unwrap();
+        assert_eq!(
+            groups.groups,
+            vec![
+                ("vehicle".to_string(), vec!["car".to_string(), "truck".to_string(), "bus".to_string()]),
+                ("animal".to_string(), vec!["cat".to_string(), "dog".to_string()]),
+            ]
+        );
+        assert!(ClassGroups::parse("").unwrap().is_empty());
+        assert!(ClassGroups::parse("vehicle").is_err());
+        assert!(ClassGroups::parse("=car").is_err());
+        assert!(ClassGroups::parse("vehicle=+").is_err());
+    }
+
+    #[test]
+    fn ungrouped_classes_have_no_display_group() {
+        let groups = ClassGroups::parse("vehicle=car+truck;road=car+bike").unwrap();
+        // 属于多个分组时显示第一个
+        assert_eq!(groups.display_name("car"), Some("vehicle"));
+        assert_eq!(groups.groups_of("car").collect::<Vec<_>>(), vec!["vehicle", "road"]);
+        assert_eq!(groups.display_name("bike"), Some("road"));
+        // 未分组的类别由调用方按原类别名显示
+        assert_eq!(groups.display_name("person"), None);
+        assert_eq!(groups.display_name("person").unwrap_or("person"), "person");
+    }
+
+    #[test]
+    fn counts_detections_per_group() {
+        let groups = ClassGroups::parse("vehicle=car+truck;road=car+bike;animal=dog").unwrap();
+        let counts = groups.count(["car", "truck", "person", "car", "bike"]);
+        assert_eq!(counts, vec![("vehicle", 3), ("road", 3), ("animal", 0)]);
+        assert_eq!(ClassGroups::default().count(["car"]), Vec::<(&str, usize)>::new());
+    }
+}
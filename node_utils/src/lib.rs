@@ -0,0 +1,15 @@
+//! 各节点共用的工具函数
+
+pub mod encoding;
+pub mod frame_gap;
+pub mod groups;
+pub mod queue;
+pub mod retry;
+pub mod watchdog;
+
+pub use encoding::{FrameEncoding, ENCODING_PARAM};
+pub use frame_gap::FrameGapTracker;
+pub use groups::ClassGroups;
+pub use queue::{BoundedQueue, OverflowPolicy, PushOutcome, SharedQueue};
+pub use retry::{retry_with_backoff, BackoffConfig};
+pub use watchdog::FrameWatchdog;
@@ -0,0 +1,214 @@
+//! 有界输出队列及溢出策略，下游消费慢时由用户选择延迟与完整性的取舍
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex};
+
+/// 队列满时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 阻塞生产者直到有空位（不丢数据）
+    Block,
+    /// 丢弃队首最旧的元素，保证下游拿到最新数据
+    DropOldest,
+    /// 丢弃新到的元素，保留已排队的数据
+    DropNewest,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "block" => Ok(OverflowPolicy::Block),
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            "drop_newest" => Ok(OverflowPolicy::DropNewest),
+            other => Err(format!("unknown overflow policy '{}'", other)),
+        }
+    }
+}
+
+/// 入队结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    Queued,
+    DroppedOldest,
+    DroppedNewest,
+}
+
+/// 固定容量队列（非线程安全），按溢出策略处理满队列；
+/// Block 策略下队满时按 DropNewest 处理，阻塞由 [`SharedQueue`] 负责
+#[derive(Debug)]
+pub struct BoundedQueue<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: u64,
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            policy,
+            dropped: 0,
+        }
+    }
+
+    pub fn push(&mut self, item: T) -> PushOutcome {
+        if self.items.len() < self.capacity {
+            self.items.push_back(item);
+            return PushOutcome::Queued;
+        }
+        self.dropped += 1;
+        match self.policy {
+            OverflowPolicy::DropOldest => {
+                self.items.pop_front();
+                self.items.push_back(item);
+                PushOutcome::DroppedOldest
+            }
+            OverflowPolicy::Block | OverflowPolicy::DropNewest => PushOutcome::DroppedNewest,
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// 累计因溢出丢弃的元素数
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// 线程安全的有界队列：生产者 push，后台发送线程 pop
+pub struct SharedQueue<T> {
+    state: Mutex<(BoundedQueue<T>, bool)>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> SharedQueue<T> {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            state: Mutex::new((BoundedQueue::new(capacity, policy), false)),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    /// 入队；Block 策略下队满时等待消费者腾出空位
+    pub fn push(&self, item: T) -> PushOutcome {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while guard.0.policy() == OverflowPolicy::Block && guard.0.is_full() && !guard.1 {
+            guard = self.not_full.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+        let outcome = guard.0.push(item);
+        self.not_empty.notify_one();
+        outcome
+    }
+
+    /// 阻塞出队；队列关闭且已取空时返回 None
+    pub fn pop(&self) -> Option<T> {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(item) = guard.0.pop() {
+                self.not_full.notify_one();
+                return Some(item);
+            }
+            if guard.1 {
+                return None;
+            }
+            guard = self.not_empty.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// 关闭队列：已排队的元素仍可取出，之后 pop 返回 None
+    pub fn close(&self) {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        guard.1 = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    /// 累计因溢出丢弃的元素数
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().map(|g| g.0.dropped()).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<T>(queue: &mut BoundedQueue<T>) -> Vec<T> {
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn drop_oldest_evicts_from_front() {
+        let mut queue = BoundedQueue::new(3, OverflowPolicy::DropOldest);
+        for i in 0..3 {
+            assert_eq!(queue.push(i), PushOutcome::Queued);
+        }
+        assert_eq!(queue.push(3), PushOutcome::DroppedOldest);
+        assert_eq!(queue.push(4), PushOutcome::DroppedOldest);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(drain(&mut queue), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_queued_items() {
+        let mut queue = BoundedQueue::new(2, OverflowPolicy::DropNewest);
+        queue.push(0);
+        queue.push(1);
+        assert_eq!(queue.push(2), PushOutcome::DroppedNewest);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(drain(&mut queue), vec![0, 1]);
+    }
+
+    #[test]
+    fn zero_capacity_holds_one_item() {
+        let mut queue = BoundedQueue::new(0, OverflowPolicy::DropOldest);
+        queue.push("a");
+        assert!(queue.is_full());
+        queue.push("b");
+        assert_eq!(drain(&mut queue), vec!["b"]);
+    }
+
+    #[test]
+    fn shared_queue_drains_after_close() {
+        let queue = SharedQueue::new(4, OverflowPolicy::Block);
+        queue.push(1);
+        queue.push(2);
+        queue.close();
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn overflow_policy_parses_names() {
+        assert_eq!("drop_oldest".parse(), Ok(OverflowPolicy::DropOldest));
+        assert_eq!(" block ".parse(), Ok(OverflowPolicy::Block));
+        assert!("oldest".parse::<OverflowPolicy>().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! 带指数退避和随机抖动的重试，用于摄像头、网络流等重连逻辑
+
+use rand::Rng;
+use std::fmt::Display;
+use std::time::Duration;
+
+/// 退避参数：第 n 次重试前等待 `min(base * multiplier^n, max)`，再叠加 ±jitter 比例的随机抖动
+#[derive(Debug, Clone)]
+pub struct BackoffConfig {
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// 抖动比例，0.2 表示在计算出的延迟上下浮动 20%
+    pub jitter: f64,
+    /// 最多尝试次数（含第一次），0 表示不限次数
+    pub max_attempts: u32,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: 5,
+        }
+    }
+}
+
+impl BackoffConfig {
+    /// 从 `<PREFIX>_BASE_MS`、`<PREFIX>_MAX_MS`、`<PREFIX>_MULTIPLIER`、`<PREFIX>_JITTER`、
+    /// `<PREFIX>_MAX_ATTEMPTS` 读取配置，缺失或非法的项使用默认值
+    pub fn from_env(prefix: &str) -> Self {
+        let defaults = Self::default();
+        let read = |suffix: &str| std::env::var(format!("{}_{}", prefix, suffix)).ok();
+        Self {
+            base_delay: read("BASE_MS")
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.base_delay),
+            max_delay: read("MAX_MS")
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_delay),
+            multiplier: read("MULTIPLIER")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|m: &f64| *m >= 1.0)
+                .unwrap_or(defaults.multiplier),
+            jitter: read("JITTER")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|j: &f64| (0.0..=1.0).contains(j))
+                .unwrap_or(defaults.jitter),
+            max_attempts: read("MAX_ATTEMPTS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.max_attempts),
+        }
+    }
+
+    /// 第 retry 次重试（从 0 开始）前的基础延迟，不含抖动，不超过 max_delay
+    pub fn delay_for_retry(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.min(i32::MAX as u32) as i32);
+        let delay = self.base_delay.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+
+    /// 在基础延迟上叠加 ±jitter 比例的均匀随机抖动
+    pub fn apply_jitter<R: Rng>(&self, delay: Duration, rng: &mut R) -> Duration {
+        if self.jitter <= 0.0 {
+            return delay;
+        }
+        let offset = rng.gen_range(-self.jitter..=self.jitter);
+        Duration::from_secs_f64((delay.as_secs_f64() * (1.0 + offset)).max(0.0))
+    }
+}
+
+/// 重复执行 `op` 直到成功或达到最大尝试次数，两次尝试之间按指数退避加抖动等待；
+/// 返回成功结果或最后一次的错误。`op` 的参数为当前尝试序号（从 1 开始）
+pub fn retry_with_backoff<T, E, F>(config: &BackoffConfig, label: &str, mut op: F) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut(u32) -> Result<T, E>,
+{
+    let mut rng = rand::thread_rng();
+    let mut attempt = 1;
+    loop {
+        match op(attempt) {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                if config.max_attempts != 0 && attempt >= config.max_attempts {
+                    eprintln!("{}: attempt {} failed: {}, giving up", label, attempt, e);
+                    return Err(e);
+                }
+                let delay = config.apply_jitter(config.delay_for_retry(attempt - 1), &mut rng);
+                eprintln!("{}: attempt {} failed: {}, retrying in {:?}", label, attempt, e, delay);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn config() -> BackoffConfig {
+        BackoffConfig {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: 5,
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max() {
+        let config = config();
+        let delays: Vec<u128> = (0..6).map(|n| config.delay_for_retry(n).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let config = config();
+        let mut rng = StdRng::seed_from_u64(7);
+        let delay = Duration::from_millis(500);
+        for _ in 0..1000 {
+            let jittered = config.apply_jitter(delay, &mut rng).as_secs_f64();
+            assert!((0.4..=0.6).contains(&jittered), "jittered delay {} out of ±20%", jittered);
+        }
+    }
+
+    #[test]
+    fn zero_jitter_keeps_delay() {
+        let config = BackoffConfig { jitter: 0.0, ..config() };
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(config.apply_jitter(Duration::from_millis(300), &mut rng), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn retry_stops_after_max_attempts() {
+        let config = BackoffConfig { base_delay: Duration::ZERO, max_attempts: 3, ..config() };
+        let mut attempts = Vec::new();
+        let result: Result<(), String> = retry_with_backoff(&config, "test", |attempt| {
+            attempts.push(attempt);
+            Err("fail".to_string())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn retry_returns_first_success() {
+        let config = BackoffConfig { base_delay: Duration::ZERO, ..config() };
+        let result: Result<u32, String> = retry_with_backoff(&config, "test", |attempt| {
+            if attempt < 2 { Err("fail".to_string()) } else { Ok(attempt) }
+        });
+        assert_eq!(result, Ok(2));
+    }
+}
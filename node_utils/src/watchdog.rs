@@ -0,0 +1,82 @@
+//! 帧看门狗：超时未收到帧时由节点退出，交给外部监管进程重启
+
+use std::time::{Duration, Instant};
+
+/// 记录最近一次收到帧的时间，超过 timeout 未再收到即视为超时；timeout 为 None 时不启用
+#[derive(Debug, Clone)]
+pub struct FrameWatchdog {
+    timeout: Option<Duration>,
+    last_frame: Instant,
+}
+
+impl FrameWatchdog {
+    /// timeout 为 0 表示不启用
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout: (!timeout.is_zero()).then_some(timeout),
+            last_frame: now,
+        }
+    }
+
+    /// 从 `NODE_FRAME_WATCHDOG_MS` 读取超时（毫秒），未设置、非法或为 0 时不启用
+    pub fn from_env(now: Instant) -> Self {
+        let timeout_ms = std::env::var("NODE_FRAME_WATCHDOG_MS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        Self::new(Duration::from_millis(timeout_ms), now)
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// 收到帧时调用，重新开始计时
+    pub fn reset(&mut self, now: Instant) {
+        self.last_frame = now;
+    }
+
+    /// 是否已超时
+    pub fn expired(&self, now: Instant) -> bool {
+        self.timeout
+            .is_some_and(|timeout| now.saturating_duration_since(self.last_frame) >= timeout)
+    }
+
+    /// 将事件等待时间缩短到不超过看门狗超时，保证超时后能及时检查
+    pub fn recv_timeout(&self, default: Duration) -> Duration {
+        self.timeout.map_or(default, |timeout| default.min(timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_after_timeout_and_recovers_on_reset() {
+        let start = Instant::now();
+        let mut watchdog = FrameWatchdog::new(Duration::from_millis(500), start);
+        assert!(!watchdog.expired(start + Duration::from_millis(499)));
+        assert!(watchdog.expired(start + Duration::from_millis(500)));
+
+        watchdog.reset(start + Duration::from_millis(600));
+        assert!(!watchdog.expired(start + Duration::from_millis(1000)));
+        assert!(watchdog.expired(start + Duration::from_millis(1100)));
+    }
+
+    #[test]
+    fn zero_timeout_disables_watchdog() {
+        let start = Instant::now();
+        let watchdog = FrameWatchdog::new(Duration::ZERO, start);
+        assert_eq!(watchdog.timeout(), None);
+        assert!(!watchdog.expired(start + Duration::from_secs(3600)));
+        assert_eq!(watchdog.recv_timeout(Duration::from_secs(1)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn recv_timeout_is_capped_by_watchdog() {
+        let watchdog = FrameWatchdog::new(Duration::from_millis(200), Instant::now());
+        assert_eq!(watchdog.recv_timeout(Duration::from_secs(1)), Duration::from_millis(200));
+        assert_eq!(watchdog.recv_timeout(Duration::from_millis(50)), Duration::from_millis(50));
+    }
+}
@@ -1,25 +1,572 @@
 use dora_node_api::{DoraNode, Event};
 use dora_node_api::arrow::array::{UInt8Array, Array};
 use opencv::{
-    core::{Mat, Scalar, Point, Rect, CV_8UC3},
+    core::{Mat, Scalar, Point, Rect, Size, CV_8UC3},
     highgui,
     imgproc::{self, LINE_8, LINE_AA, FONT_HERSHEY_SIMPLEX},
-    prelude::{MatTraitConst, MatTrait},
+    prelude::{MatTraitConst, MatTrait, VideoWriterTrait, VideoWriterTraitConst},
+    videoio::VideoWriter,
 };
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use log::{info, warn, error};
 use anyhow::{Result, Context};
-use std::str;
+use clap::{Parser, Subcommand};
+use node_utils::{ClassGroups, FrameEncoding, FrameGapTracker, FrameWatchdog, ENCODING_PARAM};
+use detection_msg::DetectionRecord;
+use frame_utils::copy_into_mat;
 
+// 一条跟踪轨迹
 #[derive(Debug, Clone)]
-struct Detection {
-    name: String,          // 检测对象的唯一标识名
-    class_name: String,    // 类别名称
-    confidence: f32,
-    x: f32,
-    y: f32,
-    width: f32,
-    height: f32,
+struct Track {
+    id: u64,
+    class_name: String,
+    bbox: (f32, f32, f32, f32), // 归一化中心点形式 (x, y, w, h)
+    first_seen: Instant,
+    last_seen: Instant,
+    missed: u32,
+}
+
+// 基于IoU的简单贪心跟踪器，用于计算目标停留时长
+struct Tracker {
+    tracks: Vec<Track>,
+    next_id: u64,
+    iou_threshold: f32,
+    max_missed: u32,
+}
+
+impl Tracker {
+    fn new(iou_threshold: f32, max_missed: u32) -> Self {
+        Self {
+            tracks: Vec::new(),
+            next_id: 1,
+            iou_threshold,
+            max_missed,
+        }
+    }
+    
+    /// 将新一帧检测结果与已有轨迹关联，为每个检测写入跟踪ID扩展，
+    /// 连续丢失超过 max_missed 次的轨迹会被移除（停留计时随之清除）
+    fn update(&mut self, detections: &mut [DetectionRecord], now: Instant) {
+        let mut matched = vec![false; self.tracks.len()];
+        
+        for detection in detections.iter_mut() {
+            let bbox = (detection.x, detection.y, detection.width, detection.height);
+            let mut best: Option<(usize, f32)> = None;
+            for (idx, track) in self.tracks.iter().enumerate() {
+                if matched[idx] || track.class_name != detection.class_name {
+                    continue;
+                }
+                let iou = box_iou(track.bbox, bbox);
+                if iou >= self.iou_threshold && best.map_or(true, |(_, b)| iou > b) {
+                    best = Some((idx, iou));
+                }
+            }
+            
+            let track = match best {
+                Some((idx, _)) => {
+                    matched[idx] = true;
+                    let track = &mut self.tracks[idx];
+                    track.bbox = bbox;
+                    track.last_seen = now;
+                    track.missed = 0;
+                    track
+                }
+                None => {
+                    self.tracks.push(Track {
+                        id: self.next_id,
+                        class_name: detection.class_name.clone(),
+                        bbox,
+                        first_seen: now,
+                        last_seen: now,
+                        missed: 0,
+                    });
+                    matched.push(true);
+                    self.next_id += 1;
+                    self.tracks.last_mut().unwrap()
+                }
+            };
+            detection.set_track_id(track.id);
+        }
+        
+        // 未匹配的轨迹计数丢失次数，超限则移除
+        for (track, was_matched) in self.tracks.iter_mut().zip(matched.iter()) {
+            if !was_matched {
+                track.missed += 1;
+            }
+        }
+        let max_missed = self.max_missed;
+        self.tracks.retain(|t| t.missed <= max_missed);
+    }
+    
+    /// 跟踪目标截至最近一次匹配时在画面中持续出现的时长（秒），轨迹不存在时为 0
+    fn dwell_secs(&self, track_id: u64) -> f32 {
+        self.tracks.iter()
+            .find(|t| t.id == track_id)
+            .map_or(0.0, |t| t.last_seen.duration_since(t.first_seen).as_secs_f32())
+    }
+}
+
+/// 计算两个归一化中心点形式框的IoU
+fn box_iou(a: (f32, f32, f32, f32), b: (f32, f32, f32, f32)) -> f32 {
+    let (ax1, ay1, ax2, ay2) = (a.0 - a.2 / 2.0, a.1 - a.3 / 2.0, a.0 + a.2 / 2.0, a.1 + a.3 / 2.0);
+    let (bx1, by1, bx2, by2) = (b.0 - b.2 / 2.0, b.1 - b.3 / 2.0, b.0 + b.2 / 2.0, b.1 + b.3 / 2.0);
+    let inter_w = (ax2.min(bx2) - ax1.max(bx1)).max(0.0);
+    let inter_h = (ay2.min(by2) - ay1.max(by1)).max(0.0);
+    let inter = inter_w * inter_h;
+    let union = a.2 * a.3 + b.2 * b.3 - inter;
+    if union <= 0.0 { 0.0 } else { inter / union }
+}
+
+/// 将归一化检测框（中心点形式）转换为裁剪到图像范围内的像素矩形
+fn pixel_rect(detection: &DetectionRecord, width: u32, height: u32) -> Rect {
+    // 将相对中心坐标转换为绝对左上角坐标
+    let x = ((detection.x - detection.width / 2.0) * width as f32) as i32;
+    let y = ((detection.y - detection.height / 2.0) * height as f32) as i32;
+    let w = (detection.width * width as f32) as i32;
+    let h = (detection.height * height as f32) as i32;
+    
+    // 确保边界框在图像范围内
+    let x = x.max(0).min(width as i32 - 1);
+    let y = y.max(0).min(height as i32 - 1);
+    let w = w.min(width as i32 - x);
+    let h = h.min(height as i32 - y);
+    
+    Rect::new(x, y, w, h)
+}
+
+const MAX_ZOOM: f32 = 8.0;
+const ZOOM_STEP: f32 = 1.25;
+const PAN_STEP: f32 = 0.1; // 每次平移当前视口宽/高的比例
+
+/// 显示窗口的缩放/平移状态：zoom 为放大倍数，center 为视口中心在原图中的归一化坐标
+#[derive(Debug, Clone, Copy)]
+struct Viewport {
+    zoom: f32,
+    center_x: f32,
+    center_y: f32,
+}
+
+impl Viewport {
+    fn new() -> Self {
+        Self { zoom: 1.0, center_x: 0.5, center_y: 0.5 }
+    }
+    
+    fn is_zoomed(&self) -> bool {
+        self.zoom > 1.0
+    }
+    
+    fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+        self.clamp_center();
+    }
+    
+    /// 按视口尺寸的比例平移，dx/dy 为 -1/0/1
+    fn pan(&mut self, dx: f32, dy: f32) {
+        self.center_x += dx * PAN_STEP / self.zoom;
+        self.center_y += dy * PAN_STEP / self.zoom;
+        self.clamp_center();
+    }
+    
+    fn center_on(&mut self, x: f32, y: f32) {
+        self.center_x = x;
+        self.center_y = y;
+        self.clamp_center();
+    }
+    
+    /// 保证视口不超出原图
+    fn clamp_center(&mut self) {
+        let half = 0.5 / self.zoom;
+        self.center_x = self.center_x.clamp(half, 1.0 - half);
+        self.center_y = self.center_y.clamp(half, 1.0 - half);
+    }
+    
+    /// 视口在原图中对应的像素区域
+    fn source_rect(&self, width: u32, height: u32) -> Rect {
+        let w = ((width as f32 / self.zoom).round() as i32).clamp(1, width as i32);
+        let h = ((height as f32 / self.zoom).round() as i32).clamp(1, height as i32);
+        let x = (self.center_x * width as f32 - w as f32 / 2.0).round() as i32;
+        let y = (self.center_y * height as f32 - h as f32 / 2.0).round() as i32;
+        Rect::new(x.clamp(0, width as i32 - w), y.clamp(0, height as i32 - h), w, h)
+    }
+    
+    /// 将显示窗口中的像素坐标换算为原图像素坐标（窗口与原图尺寸相同）
+    fn view_to_image(&self, view_x: f32, view_y: f32, width: u32, height: u32) -> (f32, f32) {
+        let source = self.source_rect(width, height);
+        (
+            source.x as f32 + view_x * source.width as f32 / width as f32,
+            source.y as f32 + view_y * source.height as f32 / height as f32,
+        )
+    }
+    
+    /// 处理缩放/平移按键，返回是否已处理
+    fn handle_key(&mut self, key: i32) -> bool {
+        match u8::try_from(key).map(char::from) {
+            Ok('+') | Ok('=') => self.zoom_by(ZOOM_STEP),
+            Ok('-') => self.zoom_by(1.0 / ZOOM_STEP),
+            Ok('0') => *self = Viewport::new(),
+            Ok('j') => self.pan(-1.0, 0.0),
+            Ok('l') => self.pan(1.0, 0.0),
+            Ok('i') => self.pan(0.0, -1.0),
+            Ok('k') => self.pan(0.0, 1.0),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// 按视口裁剪放大已标注的帧，并在右上角叠加显示全图与当前视口位置的小地图；
+/// 检测框已在原图上绘制，放大后位置保持正确
+fn render_viewport(annotated: &Mat, viewport: &Viewport, minimap_scale: f32) -> Result<Mat> {
+    let (width, height) = (annotated.cols(), annotated.rows());
+    let source = viewport.source_rect(width as u32, height as u32);
+    let mut zoomed = Mat::default();
+    imgproc::resize(&annotated.roi(source)?, &mut zoomed, Size::new(width, height), 0.0, 0.0, imgproc::INTER_LINEAR)?;
+    
+    let map_w = ((width as f32 * minimap_scale) as i32).max(1);
+    let map_h = ((height as f32 * minimap_scale) as i32).max(1);
+    if map_w + 10 > width || map_h + 10 > height {
+        return Ok(zoomed);
+    }
+    let mut minimap = Mat::default();
+    imgproc::resize(annotated, &mut minimap, Size::new(map_w, map_h), 0.0, 0.0, imgproc::INTER_AREA)?;
+    let view_rect = Rect::new(
+        (source.x as f32 * minimap_scale) as i32,
+        (source.y as f32 * minimap_scale) as i32,
+        ((source.width as f32 * minimap_scale) as i32).max(1),
+        ((source.height as f32 * minimap_scale) as i32).max(1),
+    );
+    imgproc::rectangle(&mut minimap, view_rect, Scalar::new(0.0, 255.0, 255.0, 0.0), 1, LINE_8, 0)?;
+    let inset = Rect::new(width - map_w - 10, 10, map_w, map_h);
+    minimap.copy_to(&mut zoomed.roi_mut(inset)?)?;
+    imgproc::rectangle(&mut zoomed, inset, Scalar::new(255.0, 255.0, 255.0, 0.0), 1, LINE_8, 0)?;
+    Ok(zoomed)
+}
+
+/// 陈旧检测框的不透明度：从 1 随存在时长线性降到 TTL 时的 0；ttl 为 0 时始终不透明
+fn stale_opacity(age: Duration, ttl: Duration) -> f64 {
+    if ttl.is_zero() {
+        return 1.0;
+    }
+    (1.0 - age.as_secs_f64() / ttl.as_secs_f64()).clamp(0.0, 1.0)
+}
+
+/// 以 YOLO 格式保存检测样本的数据集目录：
+/// - `<dir>/images/<frame>.jpg` 为完整帧，`<dir>/labels/<frame>.txt` 为该帧全部检测的 YOLO 标注（每条检测一行）；
+/// - `<dir>/<class>/<frame>_<idx>_<conf>.jpg` 为选中检测的框内裁剪，同名 `.txt` 为裁剪图的标注（目标占满整张裁剪图）。
+/// 类别ID按 `<dir>/classes.txt` 中的顺序分配，遇到新类别时追加
+struct DatasetWriter {
+    dir: std::path::PathBuf,
+    classes: Vec<String>,
+    low_conf: f32,  // 低于该置信度的检测自动保存，0 表示只在按键时保存
+}
+
+impl DatasetWriter {
+    fn open(dir: &str, low_conf: f32) -> Result<Self> {
+        let dir = std::path::PathBuf::from(dir);
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let classes = std::fs::read_to_string(dir.join("classes.txt"))
+            .map(|text| text.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect())
+            .unwrap_or_default();
+        Ok(Self { dir, classes, low_conf })
+    }
+    
+    fn is_hard_example(&self, detection: &DetectionRecord) -> bool {
+        detection.confidence < self.low_conf
+    }
+    
+    /// 类别ID，新类别追加到 classes.txt
+    fn class_id(&mut self, class_name: &str) -> Result<usize> {
+        if let Some(idx) = self.classes.iter().position(|c| c == class_name) {
+            return Ok(idx);
+        }
+        self.classes.push(class_name.to_string());
+        std::fs::write(self.dir.join("classes.txt"), self.classes.join("\n") + "\n")
+            .context("Failed to update classes.txt")?;
+        Ok(self.classes.len() - 1)
+    }
+    
+    /// 保存一帧：完整帧只写一次，标注包含该帧的全部检测（未标注的目标会被训练当作背景）；
+    /// selected 为需要另存裁剪样本的检测下标，返回保存的裁剪数
+    fn save(&mut self, frame: &Mat, frame_id: u64, detections: &[DetectionRecord], selected: &[usize]) -> Result<usize> {
+        let size = frame.size()?;
+        let params = opencv::core::Vector::new();
+        
+        let mut labels = String::new();
+        for detection in detections {
+            labels.push_str(&yolo_label_line(self.class_id(&detection.class_name)?, detection));
+            labels.push('\n');
+        }
+        let (images_dir, labels_dir) = (self.dir.join("images"), self.dir.join("labels"));
+        std::fs::create_dir_all(&images_dir)?;
+        std::fs::create_dir_all(&labels_dir)?;
+        opencv::imgcodecs::imwrite(&images_dir.join(format!("{}.jpg", frame_id)).to_string_lossy(), frame, &params)?;
+        std::fs::write(labels_dir.join(format!("{}.txt", frame_id)), labels)?;
+        
+        let mut crops = 0;
+        for &idx in selected {
+            let Some(detection) = detections.get(idx) else {
+                continue;
+            };
+            let rect = pixel_rect(detection, size.width as u32, size.height as u32);
+            if rect.width <= 0 || rect.height <= 0 {
+                continue;
+            }
+            let class_id = self.class_id(&detection.class_name)?;
+            let class_dir = self.dir.join(detection.class_name.replace(['/', ' '], "_"));
+            std::fs::create_dir_all(&class_dir)?;
+            let stem = format!("{}_{}_{:.2}", frame_id, idx, detection.confidence);
+            let crop = Mat::roi(frame, rect)?.try_clone()?;
+            opencv::imgcodecs::imwrite(&class_dir.join(format!("{}.jpg", stem)).to_string_lossy(), &crop, &params)?;
+            std::fs::write(class_dir.join(format!("{}.txt", stem)), format!("{} 0.500000 0.500000 1.000000 1.000000\n", class_id))?;
+            crops += 1;
+        }
+        Ok(crops)
+    }
+}
+
+/// 标注视频录制（mp4v）。VideoWriter 在收到帧后才打开，以使用实际的帧尺寸；
+/// 未配置帧率时先缓存首帧，按前两帧的到达间隔估算帧率
+struct VideoRecorder {
+    path: String,
+    fps: f64,  // 0 表示自动估算
+    writer: Option<VideoWriter>,
+    frame_size: Size,
+    first_frame: Option<(Mat, Instant)>,
+}
+
+impl VideoRecorder {
+    fn new(path: String, fps: f64) -> Self {
+        Self { path, fps, writer: None, frame_size: Size::default(), first_frame: None }
+    }
+    
+    fn write(&mut self, frame: &Mat) -> Result<()> {
+        if self.writer.is_none() {
+            let fps = if self.fps > 0.0 {
+                self.fps
+            } else {
+                match self.first_frame.take() {
+                    None => {
+                        self.first_frame = Some((frame.try_clone()?, Instant::now()));
+                        return Ok(());
+                    }
+                    Some((first, received_at)) => {
+                        let fps = (1.0 / received_at.elapsed().as_secs_f64().max(1e-3)).clamp(1.0, 120.0);
+                        self.open(&first, fps)?;
+                        self.write_frame(&first)?;
+                        fps
+                    }
+                }
+            };
+            if self.writer.is_none() {
+                self.open(frame, fps)?;
+            }
+        }
+        self.write_frame(frame)
+    }
+    
+    fn open(&mut self, frame: &Mat, fps: f64) -> Result<()> {
+        self.frame_size = frame.size()?;
+        let fourcc = VideoWriter::fourcc('m', 'p', '4', 'v')?;
+        let writer = VideoWriter::new(&self.path, fourcc, fps, self.frame_size, true)
+            .with_context(|| format!("Failed to create video writer for {}", self.path))?;
+        if !writer.is_opened()? {
+            anyhow::bail!("Failed to open {} for recording", self.path);
+        }
+        info!("Visualizer node: Recording {}x{} @ {:.1} FPS to {}", self.frame_size.width, self.frame_size.height, fps, self.path);
+        self.writer = Some(writer);
+        Ok(())
+    }
+    
+    /// 写入一帧；帧尺寸变化时缩放到录制尺寸，VideoWriter 不接受尺寸不一致的帧
+    fn write_frame(&mut self, frame: &Mat) -> Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        if frame.size()? == self.frame_size {
+            writer.write(frame)?;
+        } else {
+            let mut resized = Mat::default();
+            imgproc::resize(frame, &mut resized, self.frame_size, 0.0, 0.0, imgproc::INTER_LINEAR)?;
+            writer.write(&resized)?;
+        }
+        Ok(())
+    }
+    
+    /// 释放 VideoWriter，写完文件尾；节点退出前调用，避免文件被截断
+    fn finish(&mut self) {
+        // 只收到一帧、尚未估算出帧率时按 30 FPS 写出
+        if let Some((first, _)) = self.first_frame.take() {
+            if let Err(e) = self.open(&first, 30.0).and_then(|()| self.write_frame(&first)) {
+                warn!("Visualizer node: Failed to record the only frame: {:#}", e);
+            }
+        }
+        if let Some(mut writer) = self.writer.take() {
+            match writer.release() {
+                Ok(()) => info!("Visualizer node: Recording saved to {}", self.path),
+                Err(e) => warn!("Visualizer node: Failed to finalize recording {}: {}", self.path, e),
+            }
+        }
+    }
+}
+
+/// YOLO 标注行：`class_id cx cy w h`，坐标归一化到 [0,1]
+fn yolo_label_line(class_id: usize, detection: &DetectionRecord) -> String {
+    format!(
+        "{} {:.6} {:.6} {:.6} {:.6}",
+        class_id,
+        detection.x.clamp(0.0, 1.0),
+        detection.y.clamp(0.0, 1.0),
+        detection.width.clamp(0.0, 1.0),
+        detection.height.clamp(0.0, 1.0)
+    )
+}
+
+/// 默认标签模板，例如 `person: 95.00% 1.2s`
+const DEFAULT_LABEL_FORMAT: &str = "{class}: {conf:.2%} {dwell}s";
+
+/// 标签中由可视化节点补充、不在检测消息里的信息
+struct LabelContext<'a> {
+    group: Option<&'a str>,  // 按 VIZ_CLASS_GROUPS 归入的显示分组
+    dwell_secs: f32,         // 该跟踪目标在画面中持续出现的时长（秒）
+}
+
+/// 按模板渲染检测标签。支持的占位符：
+/// `{class}` 类别、`{name}` 对象名、`{track_id}` 跟踪ID、`{dwell}` 停留秒数、
+/// `{conf}` 置信度（可带格式，如 `{conf:.2}`、`{conf:.0%}`）、`{x}` `{y}` `{w}` `{h}` 像素坐标。
+/// 未知占位符原样保留。
+fn render_label(template: &str, detection: &DetectionRecord, context: &LabelContext, pixel_box: (i32, i32, i32, i32)) -> String {
+    let mut out = String::with_capacity(template.len() + 16);
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            // 没有闭合的花括号，剩余部分原样输出
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let placeholder = &after[..end];
+        let (key, spec) = match placeholder.split_once(':') {
+            Some((k, s)) => (k, Some(s)),
+            None => (placeholder, None),
+        };
+        let value = match key {
+            "class" => Some(detection.class_name.clone()),
+            "group" => Some(context.group.unwrap_or(detection.class_name.as_str()).to_string()),
+            "name" => Some(detection.name.clone()),
+            "track_id" => Some(detection.track_id().map_or_else(|| "-".to_string(), |id| id.to_string())),
+            "dwell" => Some(format!("{:.1}", context.dwell_secs)),
+            "conf" => format_confidence(detection.confidence, spec),
+            "x" => Some(pixel_box.0.to_string()),
+            "y" => Some(pixel_box.1.to_string()),
+            "w" => Some(pixel_box.2.to_string()),
+            "h" => Some(pixel_box.3.to_string()),
+            _ => None,
+        };
+        match value {
+            Some(v) => out.push_str(&v),
+            None => {
+                out.push('{');
+                out.push_str(placeholder);
+                out.push('}');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 按 `.N` 或 `.N%` 格式化置信度，无格式时保留两位小数；格式非法时返回 None
+fn format_confidence(confidence: f32, spec: Option<&str>) -> Option<String> {
+    let Some(spec) = spec else {
+        return Some(format!("{:.2}", confidence));
+    };
+    let (digits, percent) = match spec.strip_suffix('%') {
+        Some(d) => (d, true),
+        None => (spec, false),
+    };
+    let precision: usize = digits.strip_prefix('.')?.parse().ok()?;
+    if percent {
+        Some(format!("{:.*}%", precision, confidence * 100.0))
+    } else {
+        Some(format!("{:.*}", precision, confidence))
+    }
+}
+
+/// 读取环境变量并解析，缺失时使用默认值，非法时打印警告并回退到默认值
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => match value.trim().parse::<T>() {
+            Ok(v) => v,
+            Err(_) => {
+                warn!("Visualizer node: Invalid value '{}' for {}, using default", value, key);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+// 检测框着色方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColorMode {
+    Class,       // 按类别着色（默认）
+    Track,       // 按跟踪ID着色
+    Confidence,  // 按置信度 红→黄→绿 渐变着色
+}
+
+impl ColorMode {
+    fn from_env() -> Self {
+        match std::env::var("VIZ_COLOR_BY").as_deref() {
+            Ok("confidence") => ColorMode::Confidence,
+            Ok("track") => ColorMode::Track,
+            Ok("class") | Err(_) => ColorMode::Class,
+            Ok(other) => {
+                warn!("Visualizer node: Unknown VIZ_COLOR_BY '{}', coloring by class", other);
+                ColorMode::Class
+            }
+        }
+    }
+}
+
+fn detection_color(mode: ColorMode, detection: &DetectionRecord) -> Scalar {
+    match mode {
+        ColorMode::Class => get_class_color(&detection.class_name),
+        ColorMode::Track => detection.track_id().map_or_else(|| get_class_color(&detection.class_name), get_track_color),
+        ColorMode::Confidence => confidence_color(detection.confidence),
+    }
+}
+
+/// 置信度渐变色（BGR）：0.0 红、0.5 黄、1.0 绿，中间线性混合
+fn confidence_color(confidence: f32) -> Scalar {
+    let c = confidence.clamp(0.0, 1.0) as f64;
+    let (r, g) = if c < 0.5 {
+        (255.0, 255.0 * c * 2.0)
+    } else {
+        (255.0 * (1.0 - c) * 2.0, 255.0)
+    };
+    Scalar::new(0.0, g, r, 0.0)
+}
+
+/// 按跟踪ID从固定调色板取色，同一目标颜色保持不变
+fn get_track_color(track_id: u64) -> Scalar {
+    const PALETTE: [(f64, f64, f64); 8] = [
+        (255.0, 56.0, 56.0), (56.0, 255.0, 56.0), (56.0, 56.0, 255.0), (255.0, 157.0, 151.0),
+        (255.0, 112.0, 31.0), (72.0, 249.0, 10.0), (146.0, 204.0, 23.0), (61.0, 219.0, 134.0),
+    ];
+    let (b, g, r) = PALETTE[(track_id % PALETTE.len() as u64) as usize];
+    Scalar::new(b, g, r, 0.0)
+}
+
+/// 根据背景色亮度选择黑色或白色文字
+fn readable_text_color(background: Scalar) -> Scalar {
+    let luminance = 0.114 * background[0] + 0.587 * background[1] + 0.299 * background[2];
+    if luminance > 140.0 {
+        Scalar::new(0.0, 0.0, 0.0, 0.0)
+    } else {
+        Scalar::new(255.0, 255.0, 255.0, 0.0)
+    }
 }
 
 // 为不同类别定义颜色
@@ -45,8 +592,151 @@ fn get_class_color(class_name: &str) -> Scalar {
     Scalar::new(255.0, 0.0, 0.0, 0.0)
 }
 
+/// 绘制一个检测框、模板生成的标签和对象名；rect 为图像范围内的像素框
+fn draw_detection(mat: &mut Mat, detection: &DetectionRecord, rect: Rect, label: &str, color_mode: ColorMode) -> Result<()> {
+    let (x, y, h) = (rect.x, rect.y, rect.height);
+    
+    // 按配置的着色方式获取框颜色
+    let color = detection_color(color_mode, detection);
+    // 置信度着色时标签背景用框颜色，文字按亮度选择黑/白以保证可读
+    let (label_bg, label_fg) = match color_mode {
+        ColorMode::Confidence => (color, readable_text_color(color)),
+        _ => (Scalar::new(0.0, 0.0, 0.0, 0.0), Scalar::new(255.0, 255.0, 255.0, 0.0)),
+    };
+    
+    // 绘制矩形框
+    imgproc::rectangle(
+        mat,
+        rect,
+        color,
+        2,  // 线宽
+        LINE_8,
+        0,
+    )?;
+    
+    // 声明一个变量用于接收基线偏移量
+    let mut baseline = 0;
+    let text_size = imgproc::get_text_size(
+        label,
+        FONT_HERSHEY_SIMPLEX,
+        0.5,
+        1,
+        &mut baseline,  // 添加第5个参数：基线偏移量的可变引用
+    )?;
+    let bg_rect = Rect::new(
+        x,
+        y - text_size.height - 5,
+        text_size.width + 5,
+        text_size.height + 5,
+    );
+    imgproc::rectangle(
+        mat,
+        bg_rect,
+        label_bg, // 默认黑色背景
+        -1, // 填充矩形
+        LINE_8,
+        0,
+    )?;
+    
+    // 绘制类别标签
+    let org = Point::new(x, y - 5);
+    imgproc::put_text(
+        mat,
+        label,
+        org,
+        FONT_HERSHEY_SIMPLEX,
+        0.5,
+        label_fg, // 默认白色文字
+        1,
+        LINE_AA,
+        false,
+    )?;
+    
+    // 绘制对象ID
+    if !detection.name.is_empty() {
+        let id_org = Point::new(x, y + h + 15);
+        imgproc::put_text(
+            mat,
+            &detection.name,
+            id_org,
+            FONT_HERSHEY_SIMPLEX,
+            0.4,
+            color,
+            1,
+            LINE_AA,
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+/// 解析 `detector_node detect-image --output` 写出的检测结果（格式见 `detection_msg::to_text_line`）
+fn parse_detection_lines(text: &str) -> Result<Vec<DetectionRecord>> {
+    Ok(detection_msg::from_text_lines(text)?)
+}
+
+/// 可视化节点命令行：不带子命令时作为 Dora 节点运行
+#[derive(Parser)]
+#[command(name = "visualizer_node", about = "Detection visualizer node for Dora")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 在图片上绘制检测结果（detector_node detect-image --output 的输出）并保存
+    AnnotateImage {
+        /// 输入图片路径
+        path: String,
+        /// 检测结果文本文件
+        #[arg(long)]
+        detections: String,
+        /// 输出图片路径
+        #[arg(long)]
+        output: String,
+    },
+}
+
 fn main() -> Result<()> {
     env_logger::init();
+    
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::AnnotateImage { path, detections, output }) => run_annotate_image(&path, &detections, &output),
+        None => run_node(),
+    }
+}
+
+/// 子命令 annotate-image：按节点相同的着色与标签模板绘制检测框，写入输出图片
+fn run_annotate_image(path: &str, detections_path: &str, output: &str) -> Result<()> {
+    let mut image = opencv::imgcodecs::imread(path, opencv::imgcodecs::IMREAD_COLOR)
+        .with_context(|| format!("Failed to read image {}", path))?;
+    if image.empty() {
+        anyhow::bail!("Failed to decode image {}", path);
+    }
+    let text = std::fs::read_to_string(detections_path)
+        .with_context(|| format!("Failed to read detections {}", detections_path))?;
+    let detections = parse_detection_lines(&text)?;
+    
+    let color_mode = ColorMode::from_env();
+    let class_groups = ClassGroups::from_env("VIZ_CLASS_GROUPS");
+    let label_format: String = env_or("VIZ_LABEL_FORMAT", DEFAULT_LABEL_FORMAT.to_string());
+    let (width, height) = (image.cols() as u32, image.rows() as u32);
+    for detection in &detections {
+        let rect = pixel_rect(detection, width, height);
+        let context = LabelContext { group: class_groups.display_name(&detection.class_name), dwell_secs: 0.0 };
+        let label = render_label(&label_format, detection, &context, (rect.x, rect.y, rect.width, rect.height));
+        draw_detection(&mut image, detection, rect, &label, color_mode)?;
+    }
+    
+    opencv::imgcodecs::imwrite(output, &image, &opencv::core::Vector::new())
+        .with_context(|| format!("Failed to write {}", output))?;
+    println!("Annotated {} detections, saved to {}", detections.len(), output);
+    Ok(())
+}
+
+fn run_node() -> Result<()> {
     info!("Visualizer node: Starting...");
     
     let (_node, mut event_stream) = match DoraNode::init_from_env() {
@@ -60,27 +750,112 @@ fn main() -> Result<()> {
     info!("Visualizer node: Dora node initialized successfully");
     
     // 存储最新检测结果
-    let mut last_detections: Vec<Detection> = Vec::new();
+    let mut last_detections: Vec<DetectionRecord> = Vec::new();
+    // 最近一次收到检测结果的时间；超过 VIZ_STALE_TTL_MS 未更新时检测框逐渐淡出，0 表示不淡出
+    let mut detections_updated_at = Instant::now();
+    let stale_ttl = Duration::from_millis(env_or("VIZ_STALE_TTL_MS", 0));
     let mut frame_counter = 0;
     
+    // 目标跟踪器，用于标注停留时长
+    let mut tracker = Tracker::new(
+        env_or("VIZ_TRACK_IOU", 0.3),
+        env_or("VIZ_TRACK_MAX_MISSED", 5),
+    );
+    
+    // 上游丢帧统计
+    let mut frame_gaps = FrameGapTracker::new();
+    
+    // 困难样本数据集保存（主动学习）
+    let mut dataset_writer = match std::env::var("VIZ_DATASET_DIR") {
+        Ok(dir) => match DatasetWriter::open(&dir, env_or("VIZ_DATASET_LOW_CONF", 0.0)) {
+            Ok(writer) => {
+                info!("Visualizer node: Saving dataset samples to {} (press 's' to save the current detections)", dir);
+                Some(writer)
+            }
+            Err(e) => {
+                warn!("Visualizer node: Failed to open dataset directory, disabling it: {:#}", e);
+                None
+            }
+        },
+        Err(_) => None,
+    };
+    let mut save_requested = false;
+    
+    // 标注视频录制，无窗口（headless）时同样录制
+    let mut recorder = std::env::var("VIZ_RECORD_PATH")
+        .ok()
+        .map(|path| VideoRecorder::new(path, env_or("VIZ_RECORD_FPS", 0.0)));
+    
+    // 检测框着色方式
+    let color_mode = ColorMode::from_env();
+    
+    // 类别分组：标签可用 {group} 显示分组名，画面左上角显示各分组计数
+    let class_groups = ClassGroups::from_env("VIZ_CLASS_GROUPS");
+    
+    // 检测框标签模板
+    let label_format: String = env_or("VIZ_LABEL_FORMAT", DEFAULT_LABEL_FORMAT.to_string());
+    
+    // 缩放/平移视口；鼠标左键点击的位置在下一帧作为视口中心
+    let mut viewport = Viewport::new();
+    let minimap_scale: f32 = env_or("VIZ_MINIMAP_SCALE", 0.25);
+    let pending_click = std::sync::Arc::new(std::sync::Mutex::new(None::<(i32, i32)>));
+    
     // 尝试创建OpenCV窗口
     if highgui::named_window("Visualizer - Camera Feed with Detections", highgui::WINDOW_AUTOSIZE).is_ok() {
         info!("Visualizer node: Display window created successfully");
+        let click = pending_click.clone();
+        let callback = Box::new(move |event: i32, x: i32, y: i32, _flags: i32| {
+            if event == highgui::EVENT_LBUTTONDOWN {
+                if let Ok(mut pending) = click.lock() {
+                    *pending = Some((x, y));
+                }
+            }
+        });
+        if let Err(e) = highgui::set_mouse_callback("Visualizer - Camera Feed with Detections", Some(callback)) {
+            warn!("Visualizer node: Failed to set mouse callback: {}", e);
+        }
     } else {
         warn!("Visualizer node: Display window creation failed (headless environment?)");
         // 即使窗口创建失败，也要继续运行
     }
     
+    // 帧看门狗：超时未收到帧时以非零码退出，交给监管进程重启
+    let mut watchdog = FrameWatchdog::from_env(Instant::now());
+    if let Some(timeout) = watchdog.timeout() {
+        info!("Visualizer node: Frame watchdog enabled, exiting if no frame arrives within {:?}", timeout);
+    }
+    let recv_timeout = watchdog.recv_timeout(Duration::from_millis(1000));
+    
     info!("Visualizer node: Ready to receive data");
     
     loop {
-        if let Some(event) = event_stream.recv_timeout(Duration::from_millis(1000)) {
+        if watchdog.expired(Instant::now()) {
+            error!("Visualizer node: No frame received within {:?}, exiting for restart", watchdog.timeout().unwrap_or_default());
+            if let Some(rec) = recorder.as_mut() {
+                rec.finish();
+            }
+            let _ = highgui::destroy_all_windows();
+            std::process::exit(2);
+        }
+        if let Some(event) = event_stream.recv_timeout(recv_timeout) {
             match event {
                 Event::Input { id, data, metadata } => {
                     match id.as_str() {
                         "frame" => {
                             // 处理帧数据
                             info!("Visualizer node: Processing frame input with id 'frame'");
+                            watchdog.reset(Instant::now());
+                            
+                            // 检测上游丢帧（frame_id 不连续）
+                            let source_id = match metadata.parameters.get("frame_id") {
+                                Some(dora_node_api::Parameter::String(s)) => s.parse::<u32>().ok(),
+                                Some(dora_node_api::Parameter::Integer(i)) => Some(*i as u32),
+                                _ => None,
+                            };
+                            if let Some(missing) = source_id.and_then(|id| frame_gaps.observe(id)) {
+                                warn!("Visualizer node: Frame id gap: {} frame(s) missing upstream, {} dropped in total",
+                                      missing, frame_gaps.dropped_frames());
+                            }
                             
                             // 从元数据中获取图像尺寸
                             let width = match metadata.parameters.get("width") {
@@ -108,110 +883,83 @@ fn main() -> Result<()> {
                             let img_data: Vec<u8> = array.iter().filter_map(|x| x).collect();
                             info!("Visualizer node: Received frame with {} bytes", img_data.len());
                             
-                            // 验证数据长度与元数据中的尺寸是否匹配
-                            if img_data.len() != (width * height * 3) as usize {
-                                warn!("Visualizer node: Data size mismatch - expected {}, got {}", width * height * 3, img_data.len());
-                            }
-                            
-                            // 创建一个空的Mat，然后使用memcpy将数据复制进去
-                            let mut mat = unsafe {
-                                Mat::new_rows_cols(height as i32, width as i32, CV_8UC3)?
+                            // 按 encoding 参数取得图像：原始帧校验长度后复制进 Mat，JPEG 帧解码（尺寸以解码结果为准）
+                            let encoding = match metadata.parameters.get(ENCODING_PARAM) {
+                                Some(dora_node_api::Parameter::String(s)) => s.parse::<FrameEncoding>(),
+                                _ => Ok(FrameEncoding::Raw),
+                            };
+                            let (mut mat, width, height) = match encoding {
+                                Ok(FrameEncoding::Raw) => {
+                                    // 创建一个空的Mat，校验长度后将数据复制进去
+                                    let mut mat = unsafe {
+                                        Mat::new_rows_cols(height as i32, width as i32, CV_8UC3)?
+                                    };
+                                    if let Err(e) = copy_into_mat(&mut mat, &img_data) {
+                                        warn!("Visualizer node: Skipping frame: {:#}", e);
+                                        continue;
+                                    }
+                                    (mat, width, height)
+                                }
+                                Ok(FrameEncoding::Jpeg) => {
+                                    let buffer = opencv::core::Vector::<u8>::from_slice(&img_data);
+                                    match opencv::imgcodecs::imdecode(&buffer, opencv::imgcodecs::IMREAD_COLOR) {
+                                        Ok(mat) if !mat.empty() => {
+                                            let (w, h) = (mat.cols() as u32, mat.rows() as u32);
+                                            (mat, w, h)
+                                        }
+                                        Ok(_) => {
+                                            warn!("Visualizer node: Skipping frame: {} bytes are not a decodable JPEG", img_data.len());
+                                            continue;
+                                        }
+                                        Err(e) => {
+                                            warn!("Visualizer node: Skipping frame: JPEG decode failed: {}", e);
+                                            continue;
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    warn!("Visualizer node: Skipping frame: {}", e);
+                                    continue;
+                                }
                             };
                             
-                            // 将图像数据复制到Mat中
-                            unsafe {
-                                // 使用 data_mut 获取可变指针
-                                let data_ptr = mat.data_mut() as *mut u8;
-                                std::ptr::copy_nonoverlapping(img_data.as_ptr(), data_ptr, img_data.len());
+                            // 保存数据集样本（按键触发或低置信度检测），使用未绘制标注的原始帧
+                            if let Some(writer) = dataset_writer.as_mut() {
+                                let selected: Vec<usize> = last_detections.iter()
+                                    .enumerate()
+                                    .filter(|(_, d)| save_requested || writer.is_hard_example(d))
+                                    .map(|(idx, _)| idx)
+                                    .collect();
+                                if !selected.is_empty() {
+                                    match writer.save(&mat, frame_counter, &last_detections, &selected) {
+                                        Ok(n) => info!("Visualizer node: Saved frame {} with {} crop sample(s)", frame_counter, n),
+                                        Err(e) => warn!("Visualizer node: Failed to save dataset samples: {:#}", e),
+                                    }
+                                }
+                                save_requested = false;
                             }
                             
+                            // 检测结果未更新时按存在时长淡出：先保留未绘制的底图，绘制后按不透明度混合
+                            let box_opacity = stale_opacity(detections_updated_at.elapsed(), stale_ttl);
+                            let unannotated = if box_opacity < 1.0 { Some(mat.try_clone()?) } else { None };
+                            
                             // 在图像上绘制检测框
                             for detection in &last_detections {
-                                // 将相对坐标转换为绝对坐标
-                                let x = (detection.x * width as f32) as i32;
-                                let y = (detection.y * height as f32) as i32;
-                                let w = (detection.width * width as f32) as i32;
-                                let h = (detection.height * height as f32) as i32;
-                                
-                                // 确保边界框在图像范围内
-                                let x = x.max(0).min(width as i32 - 1);
-                                let y = y.max(0).min(height as i32 - 1);
-                                let w = w.min(width as i32 - x);
-                                let h = h.min(height as i32 - y);
-                                
-                                // 创建检测框
-                                let rect = Rect::new(x, y, w, h);
+                                // 将相对坐标转换为图像范围内的像素框
+                                let rect = pixel_rect(detection, width, height);
                                 
-                                // 获取类别颜色
-                                let color = get_class_color(&detection.class_name);
-                                
-                                // 绘制矩形框
-                                imgproc::rectangle(
-                                    &mut mat,
-                                    rect,
-                                    color,
-                                    2,  // 线宽
-                                    LINE_8,
-                                    0,
-                                )?;
-                                
-                                // 添加标签和置信度
-                                let label = format!("{}: {:.2}", detection.name, detection.confidence);
-                                let class_label = format!("{}: {:.2}%", detection.class_name, detection.confidence * 100.0);
-                                
-                                // 声明一个变量用于接收基线偏移量
-                                let mut baseline = 0;
-                                let text_size = imgproc::get_text_size(
-                                    &class_label,
-                                    FONT_HERSHEY_SIMPLEX,
-                                    0.5,
-                                    1,
-                                    &mut baseline,  // 添加第5个参数：基线偏移量的可变引用
-                                )?;
-                                let bg_rect = Rect::new(
-                                    x,
-                                    y - text_size.height - 5,
-                                    text_size.width + 5,
-                                    text_size.height + 5,
-                                );
-                                imgproc::rectangle(
-                                    &mut mat,
-                                    bg_rect,
-                                    Scalar::new(0.0, 0.0, 0.0, 0.0), // 黑色背景
-                                    -1, // 填充矩形
-                                    LINE_8,
-                                    0,
-                                )?;
-                                
-                                // 绘制类别标签
-                                let org = Point::new(x, y - 5);
-                                imgproc::put_text(
-                                    &mut mat,
-                                    &class_label,
-                                    org,
-                                    FONT_HERSHEY_SIMPLEX,
-                                    0.5,
-                                    Scalar::new(255.0, 255.0, 255.0, 0.0), // 白色文字
-                                    1,
-                                    LINE_AA,
-                                    false,
-                                )?;
-                                
-                                // 绘制对象ID
-                                if !detection.name.is_empty() {
-                                    let id_org = Point::new(x, y + h + 15);
-                                    imgproc::put_text(
-                                        &mut mat,
-                                        &detection.name,
-                                        id_org,
-                                        FONT_HERSHEY_SIMPLEX,
-                                        0.4,
-                                        color,
-                                        1,
-                                        LINE_AA,
-                                        false,
-                                    )?;
-                                }
+                                // 按模板生成标签
+                                let context = LabelContext {
+                                    group: class_groups.display_name(&detection.class_name),
+                                    dwell_secs: detection.track_id().map_or(0.0, |id| tracker.dwell_secs(id)),
+                                };
+                                let class_label = render_label(&label_format, detection, &context, (rect.x, rect.y, rect.width, rect.height));
+                                draw_detection(&mut mat, detection, rect, &class_label, color_mode)?;
+                            }
+                            if let Some(base) = unannotated {
+                                let mut blended = Mat::default();
+                                opencv::core::add_weighted(&mat, box_opacity, &base, 1.0 - box_opacity, 0.0, &mut blended, -1)?;
+                                mat = blended;
                             }
                             
                             // 显示帧计数
@@ -242,14 +990,65 @@ fn main() -> Result<()> {
                                 false,
                             )?;
                             
+                            // 显示各分组的检测数量
+                            let group_counts = class_groups.count(last_detections.iter().map(|d| d.class_name.as_str()));
+                            for (idx, (group, count)) in group_counts.iter().enumerate() {
+                                imgproc::put_text(
+                                    &mut mat,
+                                    &format!("{}: {}", group, count),
+                                    Point::new(10, 90 + 25 * idx as i32),
+                                    FONT_HERSHEY_SIMPLEX,
+                                    0.6,
+                                    Scalar::new(0.0, 255.0, 0.0, 0.0), // 绿色
+                                    2,
+                                    LINE_AA,
+                                    false,
+                                )?;
+                            }
+                            
+                            // 录制已绘制标注的完整帧（不受视口缩放影响），写入失败后停止录制
+                            if let Some(rec) = recorder.as_mut() {
+                                if let Err(e) = rec.write(&mat) {
+                                    warn!("Visualizer node: Recording failed, disabling it: {:#}", e);
+                                    rec.finish();
+                                    recorder = None;
+                                }
+                            }
+                            
+                            // 鼠标点击位置换算到原图后作为新的视口中心
+                            if let Some((click_x, click_y)) = pending_click.lock().ok().and_then(|mut p| p.take()) {
+                                let (image_x, image_y) = viewport.view_to_image(click_x as f32, click_y as f32, width, height);
+                                viewport.center_on(image_x / width as f32, image_y / height as f32);
+                            }
+                            
+                            // 放大时按视口裁剪并叠加小地图
+                            let display = if viewport.is_zoomed() {
+                                match render_viewport(&mat, &viewport, minimap_scale) {
+                                    Ok(zoomed) => zoomed,
+                                    Err(e) => {
+                                        warn!("Visualizer node: Failed to render zoomed view: {:#}", e);
+                                        mat
+                                    }
+                                }
+                            } else {
+                                mat
+                            };
+                            
                             // 显示图像
-                            if highgui::imshow("Visualizer - Camera Feed with Detections", &mat).is_ok() {
+                            if highgui::imshow("Visualizer - Camera Feed with Detections", &display).is_ok() {
                                 // 检查按键事件 (按q或ESC退出)
                                 let key = highgui::wait_key(1).unwrap_or(0);
                                 if key == 'q' as i32 || key == 27 { // 'q'键或ESC键退出
                                     info!("Visualizer node: Quit key pressed, stopping...");
                                     break;
                                 }
+                                if key == 's' as i32 && dataset_writer.is_some() { // 's'键保存下一帧的检测样本
+                                    save_requested = true;
+                                }
+                                if viewport.handle_key(key) {
+                                    info!("Visualizer node: Zoom {:.2}x centered at ({:.2}, {:.2})",
+                                          viewport.zoom, viewport.center_x, viewport.center_y);
+                                }
                             } else {
                                 warn!("Visualizer node: Failed to display image");
                             }
@@ -261,66 +1060,27 @@ fn main() -> Result<()> {
                             // 处理检测结果
                             info!("Visualizer node: Processing detections input with id 'detections'");
                             
-                            // 解析检测结果
-                            if let Some(array) = data.as_any().downcast_ref::<UInt8Array>() {
-                                let detection_data: Vec<u8> = array.iter().filter_map(|x| x).collect();
-                                info!("Visualizer node: Received {} bytes of detection data", detection_data.len());
-                                
-                                // 解析检测数据
-                                // 格式: [name(16字节), class_name(16字节), confidence(4字节), x(4字节), y(4字节), width(4字节), height(4字节)] 重复
-                                let detection_size = 16 + 16 + 4 + 4 + 4 + 4 + 4; // 52字节每检测
-                                
-                                if detection_data.len() % detection_size == 0 {
+                            // 心跳是检测节点在输入停滞时重发的旧结果，不代表检测结果有更新
+                            let heartbeat = matches!(metadata.parameters.get("heartbeat"),
+                                                     Some(dora_node_api::Parameter::String(s)) if s == "true");
+                            
+                            // 解析检测结果（Arrow StructArray，格式定义见 detection_msg）
+                            match detection_msg::from_arrow(&**data) {
+                                // 心跳重发的是已跟踪过的结果，不送入跟踪器（否则轨迹的丢失计数和停留时长会被错误刷新），
+                                // 保留当前检测框，也不刷新淡出计时
+                                Ok(_) if heartbeat => {
+                                    info!("Visualizer node: Heartbeat detections received, keeping {} current detections",
+                                          last_detections.len());
+                                }
+                                Ok(records) => {
                                     last_detections.clear();
-                                    
-                                    for chunk in detection_data.chunks(detection_size) {
-                                        if chunk.len() == detection_size {
-                                            // 解析name (16字节)
-                                            let name_bytes = &chunk[0..16];
-                                            let name = str::from_utf8(name_bytes)
-                                                .unwrap_or("")
-                                                .trim_matches('\0')
-                                                .to_string();
-                                            
-                                            // 解析class_name (16字节)
-                                            let class_bytes = &chunk[16..32];
-                                            let class_name = str::from_utf8(class_bytes)
-                                                .unwrap_or("")
-                                                .trim_matches('\0')
-                                                .to_string();
-                                            
-                                            // 解析其他字段
-                                            let confidence = f32::from_le_bytes([
-                                                chunk[32], chunk[33], chunk[34], chunk[35]
-                                            ]);
-                                            let x = f32::from_le_bytes([
-                                                chunk[36], chunk[37], chunk[38], chunk[39]
-                                            ]);
-                                            let y = f32::from_le_bytes([
-                                                chunk[40], chunk[41], chunk[42], chunk[43]
-                                            ]);
-                                            let width = f32::from_le_bytes([
-                                                chunk[44], chunk[45], chunk[46], chunk[47]
-                                            ]);
-                                            let height = f32::from_le_bytes([
-                                                chunk[48], chunk[49], chunk[50], chunk[51]
-                                            ]);
-                                            
-                                            last_detections.push(Detection {
-                                                name,
-                                                class_name,
-                                                confidence,
-                                                x,
-                                                y,
-                                                width,
-                                                height,
-                                            });
-                                        }
-                                    }
+                                    // 跳过定长模式下的哨兵记录
+                                    last_detections.extend(records.into_iter().filter(|r| !r.is_sentinel()));
+                                    tracker.update(&mut last_detections, Instant::now());
+                                    detections_updated_at = Instant::now();
                                     info!("Visualizer node: Parsed {} detections", last_detections.len());
-                                } else {
-                                    error!("Visualizer node: Invalid detection data size: {} (expected multiple of {})", detection_data.len(), detection_size);
                                 }
+                                Err(e) => error!("Visualizer node: Failed to parse detections: {}", e),
                             }
                         }
                         _ => {
@@ -343,10 +1103,199 @@ fn main() -> Result<()> {
         }
     }
     
-    // 销毁窗口
+    // 收到 Stop 事件或按键退出后结束录制，并销毁窗口
+    if let Some(rec) = recorder.as_mut() {
+        rec.finish();
+    }
     highgui::destroy_all_windows()?;
     info!("Visualizer node: Finished");
     
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_detect_image_output() {
+        let detections = parse_detection_lines("person 0.9500 0.3 0.4 0.2 0.4\n\ntraffic light 0.5 0.6 0.5 0.1 0.2\n").unwrap();
+        assert_eq!(detections.len(), 2);
+        assert_eq!(detections[0].class_name, "person");
+        assert_eq!(detections[0].confidence, 0.95);
+        assert_eq!(detections[1].class_name, "traffic light");
+        assert_eq!((detections[1].x, detections[1].y, detections[1].width, detections[1].height), (0.6, 0.5, 0.1, 0.2));
+    }
+
+    #[test]
+    fn rejects_malformed_detection_lines() {
+        assert!(parse_detection_lines("person 0.9 0.3 0.4 0.2").is_err());
+        assert!(parse_detection_lines("person 0.9 0.3 abc 0.2 0.4").is_err());
+        assert!(parse_detection_lines("0.9 0.3 0.4 0.2 0.4").is_err());
+    }
+
+    #[test]
+    fn yolo_label_line_is_normalized_and_clamped() {
+        let detection = DetectionRecord {
+            class_name: "car".to_string(),
+            confidence: 0.4,
+            x: 0.5,
+            y: 0.25,
+            width: 0.2,
+            height: 1.5,
+            ..Default::default()
+        };
+        assert_eq!(yolo_label_line(3, &detection), "3 0.500000 0.250000 0.200000 1.000000");
+    }
+
+    #[test]
+    fn dataset_writer_saves_one_frame_with_all_labels() {
+        let dir = std::env::temp_dir().join(format!("viz_dataset_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut writer = DatasetWriter::open(&dir.to_string_lossy(), 0.5).unwrap();
+        let frame = Mat::new_rows_cols_with_default(100, 200, CV_8UC3, Scalar::all(0.0)).unwrap();
+        let detection = |class_name: &str, confidence: f32, x: f32| DetectionRecord {
+            class_name: class_name.to_string(),
+            confidence,
+            x,
+            y: 0.5,
+            width: 0.2,
+            height: 0.4,
+            ..Default::default()
+        };
+        let detections = vec![detection("person", 0.9, 0.25), detection("traffic light", 0.3, 0.75)];
+        
+        assert_eq!(writer.save(&frame, 7, &detections, &[1]).unwrap(), 1);
+        assert!(dir.join("images/7.jpg").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("labels/7.txt")).unwrap(),
+            "0 0.250000 0.500000 0.200000 0.400000\n1 0.750000 0.500000 0.200000 0.400000\n"
+        );
+        assert!(dir.join("traffic_light/7_1_0.30.jpg").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("traffic_light/7_1_0.30.txt")).unwrap(),
+            "1 0.500000 0.500000 1.000000 1.000000\n"
+        );
+        assert!(!dir.join("person").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("classes.txt")).unwrap(), "person\ntraffic light\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn renders_label_templates() {
+        let mut detection = DetectionRecord {
+            name: "car_2".to_string(),
+            class_name: "car".to_string(),
+            confidence: 0.5,
+            ..Default::default()
+        };
+        let context = LabelContext { group: Some("vehicle"), dwell_secs: 1.3 };
+        let pixel_box = (10, 20, 30, 40);
+        assert_eq!(render_label(DEFAULT_LABEL_FORMAT, &detection, &context, pixel_box), "car: 50.00% 1.3s");
+        assert_eq!(render_label("{group}/{name} {conf} {conf:.1} {conf:.0%}", &detection, &context, pixel_box), "vehicle/car_2 0.50 0.5 50%");
+        assert_eq!(render_label("{x},{y} {w}x{h}", &detection, &context, pixel_box), "10,20 30x40");
+        // 未跟踪时 track_id 显示为 -，未知占位符、非法格式与未闭合的花括号原样保留
+        assert_eq!(render_label("#{track_id} {foo} {conf:x} {class", &detection, &context, pixel_box), "#- {foo} {conf:x} {class");
+        detection.set_track_id(7);
+        let context = LabelContext { group: None, dwell_secs: 0.0 };
+        assert_eq!(render_label("#{track_id} {group}", &detection, &context, pixel_box), "#7 car");
+    }
+
+    #[test]
+    fn confidence_color_blends_red_yellow_green() {
+        // BGR
+        assert_eq!(confidence_color(0.0).0, [0.0, 0.0, 255.0, 0.0]);
+        assert_eq!(confidence_color(0.25).0, [0.0, 127.5, 255.0, 0.0]);
+        assert_eq!(confidence_color(0.5).0, [0.0, 255.0, 255.0, 0.0]);
+        assert_eq!(confidence_color(0.75).0, [0.0, 255.0, 127.5, 0.0]);
+        assert_eq!(confidence_color(1.0).0, [0.0, 255.0, 0.0, 0.0]);
+        // 超出 [0, 1] 的置信度按端点取色
+        assert_eq!(confidence_color(-0.5).0, confidence_color(0.0).0);
+        assert_eq!(confidence_color(1.5).0, confidence_color(1.0).0);
+    }
+
+    #[test]
+    fn viewport_maps_view_pixels_back_to_image() {
+        let mut viewport = Viewport::new();
+        assert_eq!(viewport.view_to_image(320.0, 240.0, 640, 480), (320.0, 240.0));
+
+        // 放大 2 倍并移到右下角：视口覆盖原图 (320, 240) 起的 320x240 区域
+        viewport.zoom_by(2.0);
+        viewport.center_on(1.0, 1.0);
+        assert_eq!((viewport.center_x, viewport.center_y), (0.75, 0.75));
+        assert_eq!(viewport.view_to_image(0.0, 0.0, 640, 480), (320.0, 240.0));
+        assert_eq!(viewport.view_to_image(640.0, 480.0, 640, 480), (640.0, 480.0));
+        assert_eq!(viewport.view_to_image(100.0, 50.0, 640, 480), (370.0, 265.0));
+    }
+
+    #[test]
+    fn stale_boxes_fade_out_over_ttl() {
+        let ttl = Duration::from_millis(1000);
+        assert_eq!(stale_opacity(Duration::ZERO, ttl), 1.0);
+        assert!((stale_opacity(Duration::from_millis(250), ttl) - 0.75).abs() < 1e-9);
+        assert_eq!(stale_opacity(Duration::from_millis(1000), ttl), 0.0);
+        assert_eq!(stale_opacity(Duration::from_millis(5000), ttl), 0.0);
+        // TTL 为 0 时不淡出
+        assert_eq!(stale_opacity(Duration::from_secs(60), Duration::ZERO), 1.0);
+    }
+
+    fn tracked(class_name: &str, x: f32) -> DetectionRecord {
+        DetectionRecord {
+            class_name: class_name.to_string(),
+            confidence: 0.9,
+            x,
+            y: 0.5,
+            width: 0.2,
+            height: 0.2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tracker_keeps_id_and_accumulates_dwell_across_iou_matches() {
+        let mut tracker = Tracker::new(0.3, 2);
+        let start = Instant::now();
+        let mut frame = vec![tracked("person", 0.30), tracked("car", 0.70)];
+        tracker.update(&mut frame, start);
+        let (person, car) = (frame[0].track_id().unwrap(), frame[1].track_id().unwrap());
+        assert_ne!(person, car);
+        assert_eq!(tracker.dwell_secs(person), 0.0);
+
+        // 框轻微移动（IoU 仍高于阈值）时沿用同一ID，停留时长按首次出现累计
+        let mut frame = vec![tracked("car", 0.72), tracked("person", 0.32)];
+        tracker.update(&mut frame, start + Duration::from_millis(1500));
+        assert_eq!((frame[0].track_id(), frame[1].track_id()), (Some(car), Some(person)));
+        assert!((tracker.dwell_secs(person) - 1.5).abs() < 1e-6);
+
+        // 同位置但类别不同的检测开启新轨迹
+        let mut frame = vec![tracked("dog", 0.32)];
+        tracker.update(&mut frame, start + Duration::from_millis(1600));
+        assert!(frame[0].track_id().is_some_and(|id| id != person && id != car));
+    }
+
+    #[test]
+    fn tracker_drops_tracks_after_max_missed_frames() {
+        let mut tracker = Tracker::new(0.3, 2);
+        let start = Instant::now();
+        let mut frame = vec![tracked("person", 0.3)];
+        tracker.update(&mut frame, start);
+        let id = frame[0].track_id().unwrap();
+
+        // 连续丢失 2 帧仍保留轨迹，重新出现时沿用原ID
+        tracker.update(&mut [], start + Duration::from_secs(1));
+        tracker.update(&mut [], start + Duration::from_secs(2));
+        let mut frame = vec![tracked("person", 0.3)];
+        tracker.update(&mut frame, start + Duration::from_secs(3));
+        assert_eq!(frame[0].track_id(), Some(id));
+        assert!((tracker.dwell_secs(id) - 3.0).abs() < 1e-6);
+
+        // 丢失超过 2 帧后轨迹被移除，停留计时清零，再出现时分配新ID
+        for secs in 4..=6 {
+            tracker.update(&mut [], start + Duration::from_secs(secs));
+        }
+        assert_eq!(tracker.dwell_secs(id), 0.0);
+        let mut frame = vec![tracked("person", 0.3)];
+        tracker.update(&mut frame, start + Duration::from_secs(7));
+        assert_ne!(frame[0].track_id(), Some(id));
+    }
+}